@@ -6,11 +6,13 @@ import java.util.ArrayList;
 import java.util.List;
 import java.util.Map;
 import java.util.Objects;
+import java.util.TreeMap;
 import java.util.stream.Collectors;
 import java.util.stream.Stream;
 import java.util.stream.StreamSupport;
 
 import com.google.gson.GsonBuilder;
+import com.google.gson.JsonArray;
 import com.google.gson.JsonElement;
 import com.google.gson.JsonObject;
 import com.google.gson.JsonPrimitive;
@@ -29,6 +31,7 @@ import ghidra.program.model.listing.Instruction;
 import ghidra.program.model.listing.Program;
 import ghidra.program.model.mem.Memory;
 import ghidra.program.model.mem.MemoryAccessException;
+import ghidra.program.model.mem.MemoryBlock;
 import ghidra.program.model.symbol.Symbol;
 import ghidra.program.model.symbol.SymbolTable;
 import ghidra.util.exception.CancelledException;
@@ -79,17 +82,26 @@ class BranchDump {
         Return,
         Neutral,
         Equality,
-        Inequality
+        Inequality,
+        Switch
     }
 
     public Type type;
     public List<DestDump> dests;
+    // Jump table targets ordered by case value, for switches. Empty when the order is unknown
+    public List<DestDump> cases = List.of();
 
     private BranchDump(Type type, DestDump... dests) {
         this.type = type;
         this.dests = List.of(dests);
     }
 
+    public static BranchDump switches(List<DestDump> cases, DestDump fallback) {
+        var branch = new BranchDump(Type.Switch, fallback);
+        branch.cases = cases;
+        return branch;
+    }
+
     public static BranchDump inequality(DestDump first, DestDump second) {
         return new BranchDump(Type.Inequality, first, second);
     }
@@ -136,6 +148,34 @@ class BranchDump {
         }
     }
 
+    // The targets of a recovered jump table, one for each case ordered by case value like rizin
+    // gives them. getFlows() is sorted by address with repeats dropped, so the values come from
+    // the caseD_ labels Ghidra puts on every target instead. Null if any target has none
+    private static List<DestDump> casesFor(Instruction inst) {
+        var namespace = "switchD_" + inst.getAddress();
+        var cases = new TreeMap<Long, Address>(Long::compareUnsigned);
+        for (var target : inst.getFlows()) {
+            var labelled = false;
+            for (var symbol : inst.getProgram().getSymbolTable().getSymbols(target)) {
+                var name = symbol.getName();
+                if (!name.startsWith("caseD_") || !symbol.getParentNamespace().getName().equals(namespace)) {
+                    continue;
+                }
+                try {
+                    cases.put(Long.parseUnsignedLong(name.substring(6), 16), target);
+                    labelled = true;
+                }
+                catch (NumberFormatException e) {
+                    // Not a plain case value
+                }
+            }
+            if (!labelled) {
+                return null;
+            }
+        }
+        return cases.values().stream().map(DestDump::known).toList();
+    }
+
     public static BranchDump branchForInst(Instruction inst) throws IllegalArgumentException {
         var flow = inst.getFlowType();
 
@@ -143,6 +183,13 @@ class BranchDump {
         if (flow.isTerminal() && !flow.isCall()) {
             return BranchDump.returns();
         }
+        else if (flow.isJump() && flow.isComputed() && inst.getFlows().length > 1) {
+            // A recovered jump table. Without case labels there's no telling which target is
+            // which case, so leave the cases out rather than have them paired up wrongly
+            var cases = Objects.requireNonNullElse(casesFor(inst), List.<DestDump>of());
+            var fallback = flow.isConditional() ? fallthruFor(inst) : DestDump.unknown();
+            return BranchDump.switches(cases, fallback);
+        }
         else if (flow.isJump() || flow.isCall()) {
             var jump = jumpFor(inst);
             if (flow.isUnConditional()) {
@@ -187,6 +234,11 @@ class BranchDumpSerializer implements JsonSerializer<BranchDump> {
             case Equality, Inequality:
                 // Tuple variants are encoded as an array
                 return this.getOuterElement(value, context.serialize(value.dests));
+            case Switch:
+                var inner = new JsonArray();
+                inner.add(context.serialize(value.cases));
+                inner.add(context.serialize(value.dests.get(0)));
+                return this.getOuterElement(value, inner);
             // serde_json uses a string tag for monostate enum types
             default:
                 return new JsonPrimitive(value.type.name());
@@ -348,7 +400,79 @@ class VtableDump {
     }
 }
 
+enum Endian {
+    @SerializedName("L")
+    LITTLE,
+    @SerializedName("B")
+    BIG,
+}
+
+class SectionDump {
+    @SerializedName("N")
+    public String name;
+    @SerializedName("A")
+    public long address;
+    @SerializedName("S")
+    public long size;
+    @SerializedName("R")
+    public boolean read;
+    @SerializedName("W")
+    public boolean write;
+    @SerializedName("X")
+    public boolean exec;
+
+    public SectionDump(MemoryBlock block) {
+        this.name = block.getName();
+        this.address = block.getStart().getOffset();
+        this.size = block.getSize();
+        this.read = block.isRead();
+        this.write = block.isWrite();
+        this.exec = block.isExecute();
+    }
+}
+
+class MetadataDump {
+    @SerializedName("A")
+    public String arch;
+    @SerializedName("B")
+    public int bits;
+    @SerializedName("E")
+    public Endian endian;
+    @SerializedName("I")
+    public long base;
+    @SerializedName("S")
+    public List<SectionDump> sections;
+
+    // Names an architecture the way the native backends do
+    private static String archName(String processor, int bits) {
+        switch (processor.toLowerCase()) {
+            case "x86":
+                return bits == 64 ? "x86_64" : "x86";
+            case "aarch64":
+                return "aarch64";
+            case "arm":
+                return bits == 64 ? "aarch64" : "arm";
+            default:
+                return processor.toLowerCase();
+        }
+    }
+
+    public MetadataDump(Program program) {
+        var language = program.getLanguage();
+        this.bits = language.getLanguageDescription().getSize();
+        this.arch = archName(language.getProcessor().toString(), this.bits);
+        this.endian = language.isBigEndian() ? Endian.BIG : Endian.LITTLE;
+        this.base = program.getImageBase().getOffset();
+        this.sections = Stream.of(program.getMemory().getBlocks())
+                .filter(MemoryBlock::isLoaded)
+                .map(SectionDump::new)
+                .toList();
+    }
+}
+
 class Dump {
+    @SerializedName("M")
+    public MetadataDump metadata;
     @SerializedName("F")
     public Map<Long, FunctionDump> funcs;
     @SerializedName("V")
@@ -583,6 +707,7 @@ public class GhidraGenerator extends GhidraScript {
         this.symbolTable = this.currentProgram.getSymbolTable();
 
         var dump = new Dump();
+        dump.metadata = new MetadataDump(this.currentProgram);
         var strings = this.allStrings();
 
         this.println("Processing virtual tables");
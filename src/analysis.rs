@@ -1,21 +1,56 @@
 use colored::Colorize;
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
+use crate::compare::print_comparison;
 use crate::db::*;
+use crate::prompt::confirm;
+use crate::review::{Conflict, ConflictPolicy, Conflicts};
+use crate::strategy::{Candidate, Strategy};
 use crate::util::*;
 
-use crossterm::event;
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-
 // Silly helpers
+
+/// Whether every call in two blocks goes to the same place, going by what's bound so far
+fn calls_match(bind_db: &BindDB, pair: &ExecPair, in_blk: &Block, out_blk: &Block) -> bool {
+    out_blk.calls.len() == in_blk.calls.len()
+        && out_blk
+            .calls
+            .iter()
+            .zip(&in_blk.calls)
+            .all(|(o, i)| match (i, o) {
+                (Dest::Unknown, Dest::Unknown) => true,
+                (Dest::Known(i), Dest::Known(o)) => pair
+                    .input
+                    .fns
+                    .get(i)
+                    .and_then(|x| x.name.clone())
+                    .and_then(|x| bind_db.binds.get(&x))
+                    .map(|x| x.get_addr().map(|x| x == *o).unwrap_or(false))
+                    .unwrap_or(true),
+                _ => false,
+            })
+}
+
+/// How much two blocks agree on strings and calls, out of 2
+fn block_agreement(
+    bind_db: &BindDB,
+    pair: &ExecPair,
+    in_blk: Option<&Block>,
+    out_blk: Option<&Block>,
+) -> u32 {
+    let (Some(in_blk), Some(out_blk)) = (in_blk, out_blk) else {
+        return 0;
+    };
+    (in_blk.strings == out_blk.strings) as u32 + calls_match(bind_db, pair, in_blk, out_blk) as u32
+}
+
 fn block_compare<'a>(
     bind_db: &BindDB,
     pair: &ExecPair,
     in_blk: &'a Block,
     mut out_blks: Vec<&'a Block>,
-) -> Option<&'a Block> {
+) -> Option<(&'a Block, f32)> {
     // sanity check
     if let Some(sym_name) = pair
         .input
@@ -38,7 +73,7 @@ fn block_compare<'a>(
                 .filter(|x| x.address.function_addr == matching)
                 .collect();
             if possible.len() == 1 {
-                return Some(possible[0]);
+                return Some((possible[0], 0.9));
             } else {
                 out_blks = possible;
             }
@@ -50,44 +85,27 @@ fn block_compare<'a>(
         .iter()
         .filter(|x| x.strings == in_blk.strings)
         .collect();
-    if strings_matching.len() == 1 {
-        return Some(strings_matching[0]);
-    }
 
     // call check!
     let calls_matching: Vec<_> = out_blks
         .iter()
-        .filter(|x| x.calls.len() == in_blk.calls.len())
-        .filter(|x| {
-            x.calls
-                .iter()
-                .zip(&in_blk.calls)
-                .all(|(o, i)| match (i, o) {
-                    (Dest::Unknown, Dest::Unknown) => true,
-                    (Dest::Known(i), Dest::Known(o)) => pair
-                        .input
-                        .fns
-                        .get(&i)
-                        .map(|x| x.name.clone())
-                        .flatten()
-                        .and_then(|x| bind_db.binds.get(&x))
-                        .map(|x| x.get_addr().map(|x| x == *o).unwrap_or(false))
-                        .unwrap_or(true),
-                    _ => false,
-                })
-        })
+        .filter(|x| calls_match(bind_db, pair, in_blk, x))
         .collect();
-    if calls_matching.len() == 1 {
-        return Some(calls_matching[0]);
-    }
 
-    // do both!!
+    // do both!! Agreeing on both is the surest, so it goes first
     let both_matching = strings_matching
         .iter()
         .filter(|x| calls_matching.contains(x))
         .collect::<Vec<_>>();
     if both_matching.len() == 1 {
-        return Some(both_matching[0]);
+        return Some((both_matching[0], 0.8));
+    }
+
+    if strings_matching.len() == 1 {
+        return Some((strings_matching[0], 0.7));
+    }
+    if calls_matching.len() == 1 {
+        return Some((calls_matching[0], 0.6));
     }
 
     None
@@ -139,80 +157,80 @@ fn block_traverse<'a>(
                         .map(|x| next_blocks.push(x));
                 }
                 (
-                    Branch::Equality(Dest::Known(xeq), Dest::Known(xneq)),
-                    Branch::Equality(Dest::Known(yeq), Dest::Known(yneq)),
+                    Branch::Equality(Dest::Known(x1), Dest::Known(x2)),
+                    Branch::Equality(Dest::Known(y1), Dest::Known(y2)),
+                )
+                | (
+                    Branch::Inequality(Dest::Known(x1), Dest::Known(x2)),
+                    Branch::Inequality(Dest::Known(y1), Dest::Known(y2)),
                 ) => {
-                    //next_blocks.push((xeq, yeq));
-                    (
+                    let in_next = |x: u64| {
                         pair.input.addr_to_block(&Address {
                             function_addr: in_blk.address.function_addr,
-                            block_addr: *xeq,
-                            addr: *xeq,
-                        }),
+                            block_addr: x,
+                            addr: x,
+                        })
+                    };
+                    let out_next = |y: u64| {
                         pair.output.addr_to_block(&Address {
                             function_addr: out_blk.address.function_addr,
-                            block_addr: *yeq,
-                            addr: *yeq,
-                        }),
-                    )
-                        .as_some()
-                        .and_then(|(x, y)| (x?, y?).as_some())
-                        .map(|x| next_blocks.push(x));
+                            block_addr: y,
+                            addr: y,
+                        })
+                    };
+                    let (x1, x2, y1, y2) =
+                        (in_next(*x1), in_next(*x2), out_next(*y1), out_next(*y2));
 
-                    //next_blocks.push((xneq, yneq));
-                    (
-                        pair.input.addr_to_block(&Address {
-                            function_addr: in_blk.address.function_addr,
-                            block_addr: *xneq,
-                            addr: *xneq,
-                        }),
-                        pair.output.addr_to_block(&Address {
-                            function_addr: out_blk.address.function_addr,
-                            block_addr: *yneq,
-                            addr: *yneq,
-                        }),
-                    )
-                        .as_some()
-                        .and_then(|(x, y)| (x?, y?).as_some())
-                        .map(|x| next_blocks.push(x));
+                    // The condition may have been inverted, with the targets swapped to match,
+                    // so go with whichever way round the successors look more alike
+                    let straight =
+                        block_agreement(binds, pair, x1, y1) + block_agreement(binds, pair, x2, y2);
+                    let flipped =
+                        block_agreement(binds, pair, x1, y2) + block_agreement(binds, pair, x2, y1);
+                    let pairs = if flipped > straight {
+                        [(x1, y2), (x2, y1)]
+                    } else {
+                        [(x1, y1), (x2, y2)]
+                    };
+
+                    for (x, y) in pairs {
+                        if let (Some(x), Some(y)) = (x, y) {
+                            next_blocks.push((x, y));
+                        }
+                    }
                 }
-                (
-                    Branch::Inequality(Dest::Known(xgt), Dest::Known(xlt)),
-                    Branch::Inequality(Dest::Known(ygt), Dest::Known(ylt)),
-                ) => {
-                    //next_blocks.push((xgt, ygt));
-                    (
-                        pair.input.addr_to_block(&Address {
-                            function_addr: in_blk.address.function_addr,
-                            block_addr: *xgt,
-                            addr: *xgt,
-                        }),
-                        pair.output.addr_to_block(&Address {
-                            function_addr: out_blk.address.function_addr,
-                            block_addr: *ygt,
-                            addr: *ygt,
-                        }),
-                    )
-                        .as_some()
-                        .and_then(|(x, y)| (x?, y?).as_some())
-                        .map(|x| next_blocks.push(x));
+                (Branch::Switch(xcases, xdefault), Branch::Switch(ycases, ydefault)) => {
+                    // Cases are listed by case value, so they only line up when both tables are
+                    // the same size. A table whose order wasn't known has no cases to pair
+                    let cases: Vec<_> = if xcases.len() == ycases.len() {
+                        xcases.iter().zip(ycases).collect()
+                    } else {
+                        Vec::new()
+                    };
 
-                    //next_blocks.push((xlt, ylt));
-                    (
-                        pair.input.addr_to_block(&Address {
-                            function_addr: in_blk.address.function_addr,
-                            block_addr: *xlt,
-                            addr: *xlt,
-                        }),
-                        pair.output.addr_to_block(&Address {
-                            function_addr: out_blk.address.function_addr,
-                            block_addr: *ylt,
-                            addr: *ylt,
-                        }),
-                    )
-                        .as_some()
-                        .and_then(|(x, y)| (x?, y?).as_some())
-                        .map(|x| next_blocks.push(x));
+                    for (x, y) in cases.into_iter().chain([(xdefault, ydefault)]) {
+                        let (Dest::Known(x), Dest::Known(y)) = (x, y) else {
+                            continue;
+                        };
+                        let blocks = (
+                            pair.input.addr_to_block(&Address {
+                                function_addr: in_blk.address.function_addr,
+                                block_addr: *x,
+                                addr: *x,
+                            }),
+                            pair.output.addr_to_block(&Address {
+                                function_addr: out_blk.address.function_addr,
+                                block_addr: *y,
+                                addr: *y,
+                            }),
+                        );
+                        // Many cases tend to share a target
+                        if let (Some(x), Some(y)) = blocks {
+                            if !next_blocks.contains(&(x, y)) {
+                                next_blocks.push((x, y));
+                            }
+                        }
+                    }
                 }
 
                 _ => continue,
@@ -228,25 +246,30 @@ fn block_traverse<'a>(
     total_blocks
 }
 
+// Each xref pair is tagged with the string or symbol the xrefs point to
 pub fn xref_binds(
     bind_db: &BindDB,
     pair: &ExecPair,
-    xrefs: Vec<(&Vec<Address>, &Vec<Address>)>,
-) -> HashMap<String, u64> {
+    xrefs: Vec<(String, &Vec<Address>, &Vec<Address>)>,
+) -> HashMap<String, Candidate> {
     let mut output = HashMap::new();
 
     // Only one xref
     xrefs
         .iter()
-        .filter(|(x, y)| x.len() == 1 && y.len() == 1)
-        .filter_map(|(x, y)| {
+        .filter(|(_, x, y)| x.len() == 1 && y.len() == 1)
+        .filter_map(|(anchor, x, y)| {
             (
                 pair.input
                     .fns
                     .get(&x.first()?.function_addr)?
                     .name
                     .clone()?,
-                y.first()?.function_addr,
+                Candidate {
+                    addr: y.first()?.function_addr,
+                    score: 0.9,
+                    anchor: Some(anchor.clone()),
+                },
             )
                 .as_some()
         })
@@ -257,19 +280,19 @@ pub fn xref_binds(
     // Multiple xrefs
     xrefs
         .iter()
-        .filter(|(x, y)| x.len() > 1 && y.len() > 1)
-        .map(|(x, y)| {
+        .filter(|(_, x, y)| x.len() > 1 && y.len() > 1)
+        .map(|(anchor, x, y)| {
             let oblocks: Vec<_> = y
                 .iter()
                 .map(|x| pair.output.addr_to_block(x).unwrap())
                 .collect();
             x.iter()
                 .map(move |x| (pair.input.addr_to_block(x).unwrap(), oblocks.clone()))
-                .map(|(x, y)| (x, block_compare(bind_db, pair, x, y)?).as_some())
+                .map(move |(x, y)| (x, block_compare(bind_db, pair, x, y)?, anchor).as_some())
         })
         .flatten()
         .filter_map(|x| {
-            x.and_then(|(x, y)| {
+            x.and_then(|(x, (y, score), anchor)| {
                 (
                     pair.input
                         .fns
@@ -277,289 +300,424 @@ pub fn xref_binds(
                         .unwrap()
                         .name
                         .clone()?,
-                    y,
+                    Candidate {
+                        addr: y.address.function_addr,
+                        score,
+                        anchor: Some(anchor.clone()),
+                    },
                 )
                     .as_some()
             })
         })
         .for_each(|(x, y)| {
-            output.insert(x, y.address.function_addr);
+            output.insert(x, y);
         });
 
     output
 }
 
+// How well an input call lines up with an output one, if at all. Calls to symbols that are
+// already bound only line up with where they're bound, and outweigh any other match.
+fn call_weight(
+    bind_db: &BindDB,
+    pair: &ExecPair,
+    bound: &HashMap<u64, String>,
+    i: &Dest,
+    o: &Dest,
+) -> Option<u32> {
+    match (i, o) {
+        (Dest::Unknown, Dest::Unknown) => Some(1),
+        (Dest::Known(i), Dest::Known(o)) => {
+            let name = pair.input.fns.get(i).and_then(|x| x.name.as_ref());
+            match name.and_then(|x| bind_db.binds.get(x)) {
+                Some(Bind::Verified(x) | Bind::Unverified(x)) => (x == o).then_some(3),
+                // Inlined calls are gone from the output, so they never line up
+                Some(Bind::Inline) => None,
+                Some(Bind::Not(x)) if x.contains(o) => None,
+                // Anything but an address some other symbol is bound to
+                _ => (!bound.contains_key(o)).then_some(1),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Lines up the calls of two blocks, skipping over calls that only one side makes,
+/// giving back the index pairs that line up
+pub fn align_calls(
+    bind_db: &BindDB,
+    pair: &ExecPair,
+    bound: &HashMap<u64, String>,
+    in_calls: &[Dest],
+    out_calls: &[Dest],
+) -> Vec<(usize, usize)> {
+    let (n, m) = (in_calls.len(), out_calls.len());
+    let weight =
+        |i: usize, j: usize| call_weight(bind_db, pair, bound, &in_calls[i], &out_calls[j]);
+
+    // Heaviest common subsequence of the calls after i and j
+    let mut table = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let skip = table[i + 1][j].max(table[i][j + 1]);
+            table[i][j] = match weight(i, j) {
+                Some(w) => skip.max(table[i + 1][j + 1] + w),
+                None => skip,
+            };
+        }
+    }
+
+    let mut aligned = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        match weight(i, j) {
+            Some(w) if table[i][j] == table[i + 1][j + 1] + w => {
+                aligned.push((i, j));
+                i += 1;
+                j += 1;
+            }
+            _ if table[i][j] == table[i + 1][j] => i += 1,
+            _ => j += 1,
+        }
+    }
+    aligned
+}
+
+/// Binds the calls of paired blocks, printing the pairs whose calls don't all line up
+/// if `report` is set
 pub fn block_binds(
     bind_db: &BindDB,
     pair: &ExecPair,
     blocks: Vec<(&Block, &Block)>,
-) -> HashMap<String, u64> {
+    report: bool,
+) -> HashMap<String, Candidate> {
+    let bound = bind_db.output_names();
+
     blocks
         .into_iter()
-        .map(|(i_block, o_block)| {
-            i_block
-                .calls
-                .iter()
-                .zip(&o_block.calls)
-                .map(|(x, y)| {
-                    match (x, y) {
-                        (Dest::Unknown, Dest::Unknown) => Ok(None),
-                        (Dest::Known(i), Dest::Known(o)) => {
-                            let out = pair
-                                .input
-                                .fns
-                                .get(&i)
-                                .and_then(|x| x.name.as_ref())
-                                .map(|x| (x.clone(), *o));
-
-                            if let Some(ref x) = out {
-                                if matches!(bind_db.binds.get(&x.0), Some(Bind::Inline)) {
-                                    // stop immediately for inlines
-                                    return Err(());
-                                }
-                            }
+        .flat_map(|(i_block, o_block)| {
+            // The function the blocks were paired from
+            let anchor = pair
+                .input
+                .fns
+                .get(&i_block.address.function_addr)
+                .and_then(|x| x.name.clone());
 
-                            Ok(out)
-                        }
-                        _ => {
-                            println!(
-                                "Block mismatch! {} - {} (Potential Inline?)",
-                                i_block.address.block_addr.as_hex().blue(),
-                                o_block.address.block_addr.as_hex().blue()
-                            );
-
-                            Err(())
-                        }
-                    }
-                })
-                .take_while(|x| x.is_ok())
-                .filter_map(|x| x.unwrap())
+            let aligned = align_calls(bind_db, pair, &bound, &i_block.calls, &o_block.calls);
+            // Calls lining up around a gap are less certain than ones that all line up
+            let complete =
+                aligned.len() == i_block.calls.len() && aligned.len() == o_block.calls.len();
+            if !complete && report {
+                println!(
+                    "Block mismatch! {} - {} (Potential Inline?)",
+                    i_block.address.block_addr.as_hex().blue(),
+                    o_block.address.block_addr.as_hex().blue()
+                );
+            }
+            let score = if complete { 0.8 } else { 0.7 };
+
+            aligned.into_iter().filter_map(move |(i, o)| {
+                match (&i_block.calls[i], &o_block.calls[o]) {
+                    (Dest::Known(i), Dest::Known(o)) => (
+                        pair.input.fns.get(i)?.name.clone()?,
+                        Candidate {
+                            addr: *o,
+                            score,
+                            anchor: anchor.clone(),
+                        },
+                    )
+                        .as_some(),
+                    _ => None,
+                }
+            })
         })
-        .flatten()
         .collect()
 }
 
 // Strategies
 
-pub fn call_block_strat(pair: &ExecPair, binds: &BindDB) -> HashMap<String, u64> {
-    let call_pairs: Vec<(&Vec<Address>, &Vec<Address>)> = pair
-        .input
-        .fns
-        .iter()
-        .filter_map(|x| {
-            (
-                &x.1.xrefs,
-                &pair
-                    .output
-                    .fns
-                    .get(&binds.binds.get(x.1.name.as_ref()?)?.get_addr()?)?
-                    .xrefs,
-            )
-                .as_some()
-        })
-        .collect();
+pub struct CallBlock;
 
-    let blocks: Vec<(&Block, &Block)> = call_pairs
-        .iter()
-        .map(|(i, o)| {
-            (
-                i,
-                o.iter()
-                    .filter_map(|x| pair.output.addr_to_block(x))
-                    .collect::<Vec<_>>(),
-            )
-        })
-        .map(|(i, o)| {
-            i.iter()
-                .filter_map(|x| (x, pair.input.fns.get(&x.function_addr)?.name.as_ref()?).as_some())
-                .filter_map(|(x, y)| {
-                    (pair.input.addr_to_block(x)?, {
-                        let addr = binds.binds.get(y).and_then(|x| x.get_addr());
-
-                        let possible: Vec<_> = o
-                            .iter()
-                            // too much time lol
-                            //.filter(|y| binds.binds.values().find(|x| x.get_addr() == Some(y.address.function_addr)).is_some())
-                            .filter(|x| addr == Some(x.address.function_addr))
-                            .collect();
+impl Strategy for CallBlock {
+    fn name(&self) -> &str {
+        "call-block"
+    }
 
-                        if possible.len() == 1 {
-                            *possible[0]
-                        } else {
-                            None?
-                        }
+    fn description(&self) -> &str {
+        "Match calls inside blocks that call already bound functions"
+    }
+
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let call_pairs: Vec<(&Vec<Address>, &Vec<Address>)> = pair
+            .input
+            .fns
+            .iter()
+            .filter_map(|x| {
+                (
+                    &x.1.xrefs,
+                    &pair
+                        .output
+                        .fns
+                        .get(&binds.binds.get(x.1.name.as_ref()?)?.get_addr()?)?
+                        .xrefs,
+                )
+                    .as_some()
+            })
+            .collect();
+
+        let blocks: Vec<(&Block, &Block)> = call_pairs
+            .iter()
+            .map(|(i, o)| {
+                (
+                    i,
+                    o.iter()
+                        .filter_map(|x| pair.output.addr_to_block(x))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .map(|(i, o)| {
+                i.iter()
+                    .filter_map(|x| {
+                        (x, pair.input.fns.get(&x.function_addr)?.name.as_ref()?).as_some()
                     })
-                        .as_some()
-                })
-                .collect::<Vec<_>>()
-        })
-        .flatten()
-        .collect();
+                    .filter_map(|(x, y)| {
+                        (pair.input.addr_to_block(x)?, {
+                            let addr = binds.binds.get(y).and_then(|x| x.get_addr());
+
+                            let possible: Vec<_> = o
+                                .iter()
+                                // too much time lol
+                                //.filter(|y| binds.binds.values().find(|x| x.get_addr() == Some(y.address.function_addr)).is_some())
+                                .filter(|x| addr == Some(x.address.function_addr))
+                                .collect();
 
-    block_binds(binds, pair, blocks)
+                            if possible.len() == 1 {
+                                *possible[0]
+                            } else {
+                                None?
+                            }
+                        })
+                            .as_some()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .flatten()
+            .collect();
+
+        block_binds(binds, pair, blocks, true)
+    }
 }
 
-pub fn block_traverse_strat(pair: &ExecPair, binds: &BindDB) -> HashMap<String, u64> {
-    let fns_by_name: HashMap<_, _> = pair
-        .input
-        .fns
-        .iter()
-        .filter_map(|x| {
-            (
-                x.1.name.clone()?,
-                x.1.blocks
-                    .iter()
-                    .find(|y| y.address.block_addr == x.1.address.function_addr)?,
-            )
-                .as_some()
-        })
-        .collect();
+pub struct BlockTraverse;
 
-    // Vec<(In, Out)>
-    let block_pairs: Vec<_> = binds
-        .binds
-        .iter()
-        .filter_map(|(x, y)| {
-            (
-                *fns_by_name.get(x)?,
-                pair.output
-                    .fns
-                    .get(&y.get_addr()?)?
-                    .blocks
-                    .iter()
-                    .find(|x| Some(x.address.block_addr) == y.get_addr())?,
-            )
-                .as_some()
-        })
-        .collect();
+impl Strategy for BlockTraverse {
+    fn name(&self) -> &str {
+        "block-traverse"
+    }
 
-    block_binds(binds, pair, block_traverse(binds, pair, block_pairs))
-}
+    fn description(&self) -> &str {
+        "Walk the control flow of bound functions and match their calls"
+    }
 
-pub fn call_xref_strat(pair: &ExecPair, binds: &BindDB) -> HashMap<String, u64> {
-    let call_pairs: Vec<(&Vec<Address>, &Vec<Address>)> = pair
-        .input
-        .fns
-        .iter()
-        .filter_map(|x| {
-            (
-                &x.1.xrefs,
-                &pair
-                    .output
-                    .fns
-                    .get(&binds.binds.get(x.1.name.as_ref()?)?.get_addr()?)?
-                    .xrefs,
-            )
-                .as_some()
-        })
-        .collect();
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let fns_by_name: HashMap<_, _> = pair
+            .input
+            .fns
+            .iter()
+            .filter_map(|x| {
+                (
+                    x.1.name.clone()?,
+                    x.1.blocks
+                        .iter()
+                        .find(|y| y.address.block_addr == x.1.address.function_addr)?,
+                )
+                    .as_some()
+            })
+            .collect();
 
-    xref_binds(binds, pair, call_pairs)
+        // Vec<(In, Out)>
+        let block_pairs: Vec<_> = binds
+            .binds
+            .iter()
+            .filter_map(|(x, y)| {
+                (
+                    *fns_by_name.get(x)?,
+                    pair.output
+                        .fns
+                        .get(&y.get_addr()?)?
+                        .blocks
+                        .iter()
+                        .find(|x| Some(x.address.block_addr) == y.get_addr())?,
+                )
+                    .as_some()
+            })
+            .collect();
+
+        block_binds(binds, pair, block_traverse(binds, pair, block_pairs), true)
+    }
 }
 
-pub fn string_xref_strat(pair: &ExecPair, binds: &BindDB) -> HashMap<String, u64> {
-    let string_pairs: Vec<(&Vec<Address>, &Vec<Address>)> = pair
-        .input
-        .strings
-        .iter()
-        .filter_map(|x| (&x.1.xrefs, &pair.output.strings.get(x.0)?.xrefs).as_some())
-        .collect();
+pub struct CallXref;
 
-    xref_binds(binds, pair, string_pairs)
+impl Strategy for CallXref {
+    fn name(&self) -> &str {
+        "call-xref"
+    }
+
+    fn description(&self) -> &str {
+        "Match callers of already bound functions"
+    }
+
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let call_pairs: Vec<(String, &Vec<Address>, &Vec<Address>)> = pair
+            .input
+            .fns
+            .iter()
+            .filter_map(|x| {
+                (
+                    x.1.name.clone()?,
+                    &x.1.xrefs,
+                    &pair
+                        .output
+                        .fns
+                        .get(&binds.binds.get(x.1.name.as_ref()?)?.get_addr()?)?
+                        .xrefs,
+                )
+                    .as_some()
+            })
+            .collect();
+
+        xref_binds(binds, pair, call_pairs)
+    }
 }
 
-// The big stuff
-fn confirm(msg: &str) -> bool {
-    print!("{} {}", msg, "[y/n] ".dimmed());
-    std::io::stdout().flush().unwrap();
-
-    enable_raw_mode().unwrap();
-
-    loop {
-        let evt = event::read();
-        match evt {
-            Ok(event::Event::Key(event::KeyEvent {
-                code: event::KeyCode::Char('y'),
-                kind: event::KeyEventKind::Press,
-                ..
-            })) => {
-                disable_raw_mode().unwrap();
-                println!("yes");
-                return true;
-            }
-            Ok(event::Event::Key(event::KeyEvent {
-                code: event::KeyCode::Char('n'),
-                kind: event::KeyEventKind::Press,
-                ..
-            })) => {
-                disable_raw_mode().unwrap();
-                println!("no");
-                return false;
-            }
+pub struct StringXref;
 
-            Ok(event::Event::Key(event::KeyEvent {
-                code: event::KeyCode::Char('c'),
-                modifiers: event::KeyModifiers::CONTROL,
-                ..
-            })) => {
-                disable_raw_mode().unwrap();
-                std::process::exit(0);
-            }
-            _ => (),
-        };
+impl Strategy for StringXref {
+    fn name(&self) -> &str {
+        "string-xref"
+    }
+
+    fn description(&self) -> &str {
+        "Match functions referencing the same strings"
+    }
+
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let string_pairs: Vec<(String, &Vec<Address>, &Vec<Address>)> = pair
+            .input
+            .strings
+            .iter()
+            .filter_map(|x| {
+                (
+                    x.0.clone(),
+                    &x.1.xrefs,
+                    &pair.output.strings.get(x.0)?.xrefs,
+                )
+                    .as_some()
+            })
+            .collect();
+
+        xref_binds(binds, pair, string_pairs)
     }
 }
 
-fn conflict_confirm(sym: &str, addr: u64) -> bool {
-    let sym_demangle = cpp_demangle::Symbol::new(sym)
-        .map(|x| x.to_string())
-        .unwrap_or(sym.to_string());
+// The big stuff
+fn conflict_confirm(pair: &ExecPair, names: &HashMap<u64, String>, sym: &str, addr: u64) -> bool {
+    print_comparison(pair, names, sym, addr);
 
     confirm(&format!(
         "Is {} located at {}",
-        sym_demangle.yellow(),
+        demangle(sym).yellow(),
         addr.as_hex().blue()
     ))
 }
 
 impl BindDB {
-    pub fn process(&mut self, new: HashMap<String, u64>, outfile: &Path) {
+    /// Merges proposed binds into the database and returns how many symbols were added or verified.
+    /// Conflicts are asked about unless a policy is given, in which case they go to the review queue.
+    pub fn process(
+        &mut self,
+        pair: &ExecPair,
+        new: HashMap<String, Candidate>,
+        strategy: &str,
+        round: usize,
+        outfile: &Path,
+        conflicts: &mut Conflicts,
+    ) -> usize {
         let before_count = self.binds.len();
+        let before_syms: HashSet<String> = self.binds.keys().cloned().collect();
         let mut verify_count = 0;
+        let policy = conflicts.policy;
+        let names = self.output_names();
 
         println!(
             "Processing {} new symbols",
             new.len().to_string().bright_green()
         );
 
-        for (k, v) in new {
+        for (k, cand) in new {
+            let v = cand.addr;
+            self.add_evidence(
+                &k,
+                Evidence {
+                    addr: v,
+                    score: cand.score,
+                    strategy: strategy.to_string(),
+                    anchor: cand.anchor,
+                    round,
+                },
+            );
+
             if let Some(x) = self.binds.get_mut(&k) {
-                match x {
-                    Bind::Unverified(a) => {
-                        if *a != v {
-                            // CONFLICT
-                            if conflict_confirm(&k, v) {
-                                verify_count += 1;
-                                *x = Bind::Verified(v);
-                            } else if conflict_confirm(&k, *a) {
-                                verify_count += 1;
-                                *x = Bind::Verified(*a);
-                            } else {
-                                *x = Bind::Not(vec![*a, v]);
+                let conflicting = match x {
+                    Bind::Unverified(a) => *a != v,
+                    Bind::Not(a) => !a.contains(&v),
+                    Bind::Verified(_) | Bind::Inline => false,
+                };
+
+                if conflicting {
+                    let conflict = Conflict::Address {
+                        symbol: k.clone(),
+                        existing: x.clone(),
+                        proposed: v,
+                    };
+
+                    if let Some(policy) = policy {
+                        conflicts.push(
+                            conflict,
+                            (policy != ConflictPolicy::Defer).then_some(policy),
+                        );
+
+                        *x = policy.resolve(x, v);
+                    } else {
+                        // Queued until answered so it isn't lost if we get killed
+                        conflicts.push(conflict.clone(), None);
+
+                        match x {
+                            Bind::Unverified(a) => {
+                                if conflict_confirm(pair, &names, &k, v) {
+                                    verify_count += 1;
+                                    *x = Bind::Verified(v);
+                                } else if conflict_confirm(pair, &names, &k, *a) {
+                                    verify_count += 1;
+                                    *x = Bind::Verified(*a);
+                                } else {
+                                    *x = Bind::Not(vec![*a, v]);
+                                }
                             }
-                        }
-                    }
-                    Bind::Not(a) => {
-                        if a.iter().find(|x| **x == v).is_none() {
-                            if conflict_confirm(&k, v) {
-                                verify_count += 1;
-                                *x = Bind::Verified(v);
-                            } else {
-                                a.push(v);
+                            Bind::Not(a) => {
+                                if conflict_confirm(pair, &names, &k, v) {
+                                    verify_count += 1;
+                                    *x = Bind::Verified(v);
+                                } else {
+                                    a.push(v);
+                                }
                             }
+                            Bind::Verified(_) | Bind::Inline => {}
                         }
+
+                        conflicts.remove(&conflict);
                     }
-                    Bind::Verified(_) | Bind::Inline => {}
                 }
             } else {
                 self.binds.insert(k, Bind::Unverified(v));
@@ -580,6 +738,9 @@ impl BindDB {
                 //println!("{:?}", appearances);
 
                 if appearances.len() > 1 {
+                    // The symbols allowed to stay unverified
+                    let mut keep = Vec::new();
+
                     println!("{:?}", appearances);
                     if let Some(verified) = appearances
                         .iter()
@@ -587,10 +748,44 @@ impl BindDB {
                     {
                         self.binds
                             .insert(verified.0.to_string(), verified.1.clone());
+                    } else if let Some(policy) = policy {
+                        let candidates: Vec<_> = appearances
+                            .iter()
+                            .filter(|x| match policy {
+                                ConflictPolicy::KeepExisting | ConflictPolicy::Defer => {
+                                    before_syms.contains(x.0)
+                                }
+                                ConflictPolicy::PreferNew => !before_syms.contains(x.0),
+                                ConflictPolicy::MarkNot => false,
+                            })
+                            .collect();
+                        // Deferring leaves whatever was bound before this run for the review,
+                        // and only the newly proposed symbols are dropped
+                        if candidates.len() == 1 || policy == ConflictPolicy::Defer {
+                            keep = candidates.iter().map(|x| x.0).collect();
+                        }
+
+                        if policy == ConflictPolicy::MarkNot {
+                            for bind in &appearances {
+                                self.binds.insert(bind.0.to_string(), Bind::Not(vec![*a]));
+                            }
+                        }
+
+                        let mut symbols: Vec<_> = appearances.iter().map(|x| x.0.clone()).collect();
+                        symbols.sort();
+                        conflicts.push(
+                            Conflict::Duplicate { symbols, addr: *a },
+                            (policy != ConflictPolicy::Defer).then_some(policy),
+                        );
                     } else {
+                        let mut symbols: Vec<_> = appearances.iter().map(|x| x.0.clone()).collect();
+                        symbols.sort();
+                        let conflict = Conflict::Duplicate { symbols, addr: *a };
+                        conflicts.push(conflict.clone(), None);
+
                         for bind in &appearances {
                             println!("{:?}", bind);
-                            if conflict_confirm(bind.0, *a) {
+                            if conflict_confirm(pair, &names, bind.0, *a) {
                                 verify_count += 1;
                                 self.binds.insert(bind.0.to_string(), Bind::Verified(*a));
                                 break;
@@ -598,9 +793,15 @@ impl BindDB {
                                 self.binds.insert(bind.0.to_string(), Bind::Not(vec![*a]));
                             }
                         }
+
+                        conflicts.remove(&conflict);
                     }
 
                     for bind in appearances {
+                        if keep.contains(&bind.0) {
+                            continue;
+                        }
+
                         if let Some(Bind::Unverified(_)) = self.binds.get(bind.0) {
                             self.binds.remove(bind.0);
                         }
@@ -611,10 +812,9 @@ impl BindDB {
             }
         }
 
-        println!(
-            "Added {} symbols",
-            (self.binds.len() - before_count).to_string().bright_green()
-        );
+        let added_count = self.binds.len().saturating_sub(before_count);
+
+        println!("Added {} symbols", added_count.to_string().bright_green());
 
         if verify_count > 0 {
             println!(
@@ -622,11 +822,75 @@ impl BindDB {
                 verify_count.to_string().bright_green()
             );
         }
+
+        added_count + verify_count
+    }
+
+    /// Runs the strategies in order, repeating until a round finds nothing new
+    pub fn run(
+        &mut self,
+        pair: &ExecPair,
+        strats: &[&dyn Strategy],
+        max_rounds: usize,
+        outfile: &Path,
+        policy: Option<ConflictPolicy>,
+    ) {
+        let mut conflicts = Conflicts::new(outfile, policy);
+
+        for round in 1..=max_rounds {
+            println!("{}", format!("Round {}", round).bold());
+
+            let mut round_count = 0;
+            for strat in strats {
+                println!("Running {}", strat.name().cyan());
+
+                let count = self.process(
+                    pair,
+                    strat.run(pair, self),
+                    strat.name(),
+                    round,
+                    outfile,
+                    &mut conflicts,
+                );
+                println!(
+                    "{} found {} new symbols",
+                    strat.name().cyan(),
+                    count.to_string().bright_green()
+                );
+
+                round_count += count;
+            }
+
+            println!(
+                "Round {} found {} new symbols",
+                round,
+                round_count.to_string().bright_green()
+            );
+
+            if round_count == 0 {
+                break;
+            }
+
+            if round == max_rounds {
+                println!("Stopped after {} rounds", max_rounds);
+            }
+        }
+
+        let pending = conflicts.queue.pending().count();
+        if pending > 0 {
+            println!(
+                "{} conflicts waiting for review in {}",
+                pending.to_string().bright_green(),
+                conflicts.path().display()
+            );
+        }
     }
 
     pub fn new(pair: &ExecPair) -> Self {
         let mut bind_db = BindDB {
             binds: HashMap::new(),
+            evidence: HashMap::new(),
+            base: (pair.output.meta.base != 0).then_some(pair.output.meta.base),
         };
 
         // Vtables
@@ -639,11 +903,21 @@ impl BindDB {
                     x.function_addrs.iter().map(|x| pair.input.fns.get(x)),
                 )
             })
-            .filter_map(|(x, y)| y.zip(&pair.output.vtables.get(x)?.function_addrs).as_some())
-            .flatten()
-            .filter_map(|(i, o)| (i?.name.clone()?, Bind::Verified(*o)).as_some())
-            .for_each(|(x, y)| {
-                bind_db.binds.insert(x, y);
+            .filter_map(|(x, y)| (x, y.zip(&pair.output.vtables.get(x)?.function_addrs)).as_some())
+            .flat_map(|(x, y)| y.map(move |y| (x, y)))
+            .filter_map(|(x, (i, o))| (x, i?.name.clone()?, *o).as_some())
+            .for_each(|(x, y, z)| {
+                bind_db.binds.insert(y.clone(), Bind::Verified(z));
+                bind_db.add_evidence(
+                    &y,
+                    Evidence {
+                        addr: z,
+                        score: 1.0,
+                        strategy: String::from("vtable"),
+                        anchor: Some(x.clone()),
+                        round: 0,
+                    },
+                );
             });
 
         // Do a little string xref
@@ -652,3 +926,113 @@ impl BindDB {
         bind_db
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec(names: &[(u64, &str)]) -> ExecDB {
+        ExecDB {
+            header: ExecHeader::default(),
+            meta: Metadata::default(),
+            fns: names
+                .iter()
+                .map(|&(addr, name)| {
+                    let address = Address {
+                        addr,
+                        block_addr: addr,
+                        function_addr: addr,
+                    };
+                    let func = Function {
+                        name: Some(name.to_string()),
+                        address,
+                        blocks: Vec::new(),
+                        xrefs: Vec::new(),
+                    };
+                    (addr, func)
+                })
+                .collect(),
+            vtables: HashMap::new(),
+            strings: HashMap::new(),
+        }
+    }
+
+    fn setup() -> (ExecPair, BindDB) {
+        let pair = ExecPair {
+            input: exec(&[(0x100, "a"), (0x200, "b"), (0x300, "c"), (0x400, "d")]),
+            output: exec(&[]),
+        };
+        let binds = BindDB {
+            binds: HashMap::from([
+                ("a".to_string(), Bind::Verified(0x1100)),
+                ("b".to_string(), Bind::Unverified(0x1200)),
+                ("c".to_string(), Bind::Not(vec![0x1300])),
+                ("d".to_string(), Bind::Inline),
+            ]),
+            evidence: HashMap::new(),
+            base: None,
+        };
+        (pair, binds)
+    }
+
+    fn align(in_calls: &[u64], out_calls: &[u64]) -> Vec<(usize, usize)> {
+        let (pair, binds) = setup();
+        let dests = |x: &[u64]| x.iter().map(|x| Dest::Known(*x)).collect::<Vec<_>>();
+        align_calls(
+            &binds,
+            &pair,
+            &binds.output_names(),
+            &dests(in_calls),
+            &dests(out_calls),
+        )
+    }
+
+    #[test]
+    fn align_calls_skips_calls_one_side_makes() {
+        assert_eq!(align(&[0x100, 0x200], &[0x1100, 0x1200]), [(0, 0), (1, 1)]);
+        // An extra call in the middle only leaves a gap
+        assert_eq!(
+            align(&[0x100, 0x300, 0x200], &[0x1100, 0x1200]),
+            [(0, 0), (2, 1)]
+        );
+        assert_eq!(
+            align(&[0x100, 0x200], &[0x1100, 0x1500, 0x1200]),
+            [(0, 0), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn align_calls_prefers_bound_calls() {
+        // a could line up with the unbound call, but lining up with its bind is worth more
+        assert_eq!(align(&[0x100], &[0x1500, 0x1100]), [(0, 1)]);
+        // Order still has to be kept, so only one of two swapped calls lines up
+        assert_eq!(align(&[0x200, 0x100], &[0x1100, 0x1200]).len(), 1);
+    }
+
+    #[test]
+    fn align_calls_respects_binds() {
+        // Inlined calls never line up
+        assert_eq!(align(&[0x400], &[0x1500]), []);
+        // Nor do addresses the symbol is known not to be at, or that belong to another symbol
+        assert_eq!(align(&[0x300], &[0x1300]), []);
+        assert_eq!(align(&[0x300], &[0x1100]), []);
+        assert_eq!(align(&[0x300], &[0x1500]), [(0, 0)]);
+    }
+
+    #[test]
+    fn align_calls_lines_up_unknown_calls_with_each_other() {
+        let (pair, binds) = setup();
+        let align = |in_calls: &[Dest], out_calls: &[Dest]| {
+            align_calls(&binds, &pair, &binds.output_names(), in_calls, out_calls)
+        };
+        assert_eq!(
+            align(
+                &[Dest::Unknown, Dest::Known(0x100)],
+                &[Dest::Unknown, Dest::Known(0x1100)]
+            ),
+            [(0, 0), (1, 1)]
+        );
+        assert_eq!(align(&[Dest::Unknown], &[Dest::Known(0x1500)]), []);
+        assert_eq!(align(&[Dest::Known(0x300)], &[Dest::Unknown]), []);
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::{HashMap, HashSet};
+use std::mem::{discriminant, Discriminant};
+
+use crate::analysis::{align_calls, block_binds};
+use crate::db::*;
+use crate::strategy::{Candidate, Strategy};
+use crate::util::*;
+
+/// Blocks below this similarity are never paired
+const MIN_BLOCK_SIMILARITY: f32 = 0.5;
+/// Functions that align worse than this are left alone
+const MIN_FUNCTION_SIMILARITY: f32 = 0.3;
+/// Functions are only aligned when there are at most this many block pairs to compare
+const MAX_ALIGN_PAIRS: usize = 1 << 18;
+/// Functions with fewer blocks are too plain for their shape to tell them apart
+const MIN_FINGERPRINT_BLOCKS: usize = 3;
+
+/// How two bound functions' control flow lines up
+pub struct FunctionAlignment<'a> {
+    /// Paired input and output blocks, in flow order
+    pub blocks: Vec<(&'a Block, &'a Block)>,
+    /// From 0 to 1, where 1 means every block found a perfect partner
+    pub similarity: f32,
+}
+
+/// Where a branch can go
+fn successors(branch: &Branch) -> Vec<u64> {
+    let dests: Vec<&Dest> = match branch {
+        Branch::Return => Vec::new(),
+        Branch::Neutral(x) => vec![x],
+        Branch::Equality(x, y) | Branch::Inequality(x, y) => vec![x, y],
+        Branch::Switch(cases, default) => cases.iter().chain([default]).collect(),
+    };
+    dests
+        .into_iter()
+        .filter_map(|x| match x {
+            Dest::Known(x) => Some(*x),
+            Dest::Unknown => None,
+        })
+        .collect()
+}
+
+/// A function's blocks in reverse postorder from its entry, then whatever can't be reached
+/// in address order, which keeps the order stable when blocks are moved around
+fn flow_order(func: &Function) -> Vec<&Block> {
+    let by_addr: HashMap<u64, &Block> = func
+        .blocks
+        .iter()
+        .map(|x| (x.address.block_addr, x))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut postorder = Vec::new();
+    // (block, whether its successors have been pushed yet)
+    let mut stack = vec![(func.address.function_addr, false)];
+    while let Some((addr, expanded)) = stack.pop() {
+        let Some(block) = by_addr.get(&addr) else {
+            continue;
+        };
+        if expanded {
+            postorder.push(*block);
+            continue;
+        }
+        if !seen.insert(addr) {
+            continue;
+        }
+
+        stack.push((addr, true));
+        // Successors keep the order the branch gives them in, so the taken side of a
+        // condition comes first wherever the compiler happened to lay it out
+        let mut next = successors(&block.branch);
+        let mut pushed = HashSet::new();
+        next.retain(|x| pushed.insert(*x));
+        stack.extend(next.into_iter().rev().map(|x| (x, false)));
+    }
+    postorder.reverse();
+
+    let mut rest: Vec<_> = func
+        .blocks
+        .iter()
+        .filter(|x| !seen.contains(&x.address.block_addr))
+        .collect();
+    rest.sort_by_key(|x| x.address.block_addr);
+    postorder.extend(rest);
+    postorder
+}
+
+/// How alike two blocks are going by their strings, calls and how they branch, from 0 to 1.
+/// Anything under `MIN_BLOCK_SIMILARITY` comes back as 0
+fn block_similarity(
+    binds: &BindDB,
+    pair: &ExecPair,
+    bound: &HashMap<u64, String>,
+    (in_blk, in_strs): (&Block, &HashSet<&String>),
+    (out_blk, out_strs): (&Block, &HashSet<&String>),
+) -> f32 {
+    // (weight, score)
+    let mut parts = vec![(
+        0.5,
+        (discriminant(&in_blk.branch) == discriminant(&out_blk.branch)) as u32 as f32,
+    )];
+
+    if !in_strs.is_empty() || !out_strs.is_empty() {
+        let shared = in_strs.intersection(out_strs).count();
+        parts.push((1.0, shared as f32 / in_strs.union(out_strs).count() as f32));
+    }
+
+    let (in_calls, out_calls) = (in_blk.calls.len(), out_blk.calls.len());
+    if in_calls != 0 || out_calls != 0 {
+        // Aligning the calls is the costly part, so skip it when even a perfect alignment
+        // wouldn't be enough
+        let best = 2.0 * in_calls.min(out_calls) as f32 / (in_calls + out_calls) as f32;
+        let total: f32 = parts.iter().map(|x| x.0).sum::<f32>() + 1.0;
+        let most = (parts.iter().map(|(w, x)| w * x).sum::<f32>() + best) / total;
+        if most < MIN_BLOCK_SIMILARITY {
+            return 0.0;
+        }
+
+        let aligned = align_calls(binds, pair, bound, &in_blk.calls, &out_blk.calls);
+        parts.push((
+            1.0,
+            2.0 * aligned.len() as f32 / (in_calls + out_calls) as f32,
+        ));
+    }
+
+    let total: f32 = parts.iter().map(|x| x.0).sum();
+    let similarity = parts.iter().map(|(w, x)| w * x).sum::<f32>() / total;
+    if similarity < MIN_BLOCK_SIMILARITY {
+        0.0
+    } else {
+        similarity
+    }
+}
+
+/// Aligns the blocks of two functions as sequences in flow order, so blocks added or
+/// removed on one side only leave a gap instead of throwing off everything after them
+pub fn align_functions<'a>(
+    binds: &BindDB,
+    pair: &ExecPair,
+    bound: &HashMap<u64, String>,
+    in_fn: &'a Function,
+    out_fn: &'a Function,
+) -> FunctionAlignment<'a> {
+    let in_blks = flow_order(in_fn);
+    let out_blks = flow_order(out_fn);
+    let (n, m) = (in_blks.len(), out_blks.len());
+
+    let strings = |blks: &[&'a Block]| -> Vec<HashSet<&'a String>> {
+        blks.iter().map(|x| x.strings.iter().collect()).collect()
+    };
+    let (in_strs, out_strs) = (strings(&in_blks), strings(&out_blks));
+
+    let sims: Vec<Vec<f32>> = in_blks
+        .iter()
+        .zip(&in_strs)
+        .map(|x| {
+            out_blks
+                .iter()
+                .zip(&out_strs)
+                .map(|y| block_similarity(binds, pair, bound, (x.0, x.1), (y.0, y.1)))
+                .collect()
+        })
+        .collect();
+
+    // Best total similarity of the blocks after i and j
+    let mut table = vec![vec![0f32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let skip = table[i + 1][j].max(table[i][j + 1]);
+            table[i][j] = if sims[i][j] > 0.0 {
+                skip.max(table[i + 1][j + 1] + sims[i][j])
+            } else {
+                skip
+            };
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if sims[i][j] > 0.0 && table[i][j] == table[i + 1][j + 1] + sims[i][j] {
+            blocks.push((in_blks[i], out_blks[j]));
+            i += 1;
+            j += 1;
+        } else if table[i][j] == table[i + 1][j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    FunctionAlignment {
+        blocks,
+        similarity: if n.max(m) == 0 {
+            0.0
+        } else {
+            table[0][0] / n.max(m) as f32
+        },
+    }
+}
+
+pub struct CfgAlign;
+
+impl Strategy for CfgAlign {
+    fn name(&self) -> &str {
+        "cfg-align"
+    }
+
+    fn description(&self) -> &str {
+        "Align the whole control flow of bound functions and match their calls"
+    }
+
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let bound = binds.output_names();
+        let mut out: HashMap<String, Candidate> = HashMap::new();
+
+        let fn_pairs = pair.input.fns.values().filter_map(|x| {
+            let addr = binds.binds.get(x.name.as_ref()?)?.get_addr()?;
+            (x, pair.output.fns.get(&addr)?).as_some()
+        });
+
+        for (in_fn, out_fn) in fn_pairs {
+            // Every block is compared with every other, which huge functions can't afford
+            if in_fn.blocks.len() * out_fn.blocks.len() > MAX_ALIGN_PAIRS {
+                continue;
+            }
+
+            let alignment = align_functions(binds, pair, &bound, in_fn, out_fn);
+            if alignment.similarity < MIN_FUNCTION_SIMILARITY {
+                continue;
+            }
+
+            for (sym, mut candidate) in block_binds(binds, pair, alignment.blocks, false) {
+                candidate.score *= alignment.similarity;
+                match out.get(&sym) {
+                    Some(x) if x.score >= candidate.score => {}
+                    _ => {
+                        out.insert(sym, candidate);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// The shape of a block, without any addresses
+#[derive(Hash, PartialEq, Eq)]
+struct BlockShape {
+    branch: Discriminant<Branch>,
+    out_degree: usize,
+    in_degree: usize,
+    calls: usize,
+    strings: usize,
+}
+
+/// The shape of a function, which stays the same wherever it's loaded
+#[derive(Hash, PartialEq, Eq)]
+struct Fingerprint {
+    blocks: Vec<BlockShape>,
+    callers: usize,
+    callees: usize,
+}
+
+fn fingerprint(func: &Function) -> Fingerprint {
+    let mut in_degree: HashMap<u64, usize> = HashMap::new();
+    for block in &func.blocks {
+        for x in successors(&block.branch) {
+            *in_degree.entry(x).or_default() += 1;
+        }
+    }
+
+    let callees: HashSet<_> = func
+        .blocks
+        .iter()
+        .flat_map(|x| &x.calls)
+        .filter_map(|x| match x {
+            Dest::Known(x) => Some(*x),
+            Dest::Unknown => None,
+        })
+        .collect();
+
+    Fingerprint {
+        blocks: flow_order(func)
+            .into_iter()
+            .map(|x| BlockShape {
+                branch: discriminant(&x.branch),
+                out_degree: successors(&x.branch).len(),
+                in_degree: in_degree.get(&x.address.block_addr).copied().unwrap_or(0),
+                calls: x.calls.len(),
+                strings: x.strings.len(),
+            })
+            .collect(),
+        callers: func.xrefs.len(),
+        callees: callees.len(),
+    }
+}
+
+/// Fingerprints that only one function in the exec has, with that function
+fn unique_fingerprints(exec: &ExecDB) -> HashMap<Fingerprint, &Function> {
+    let mut seen: HashMap<Fingerprint, Option<&Function>> = HashMap::new();
+    for func in exec.fns.values() {
+        if func.blocks.len() < MIN_FINGERPRINT_BLOCKS {
+            continue;
+        }
+        seen.entry(fingerprint(func))
+            .and_modify(|x| *x = None)
+            .or_insert(Some(func));
+    }
+    seen.into_iter()
+        .filter_map(|(k, v)| (k, v?).as_some())
+        .collect()
+}
+
+pub struct StructHash;
+
+impl Strategy for StructHash {
+    fn name(&self) -> &str {
+        "struct-hash"
+    }
+
+    fn description(&self) -> &str {
+        "Match functions whose control flow shape is unique on both sides"
+    }
+
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate> {
+        let bound = binds.output_names();
+        let outputs = unique_fingerprints(&pair.output);
+
+        unique_fingerprints(&pair.input)
+            .into_iter()
+            .filter_map(|(print, in_fn)| {
+                let name = in_fn.name.clone()?;
+                let addr = outputs.get(&print)?.address.function_addr;
+
+                // Nothing anchors these, so leave anything already decided alone
+                let rejected = match binds.binds.get(&name) {
+                    Some(Bind::Not(x)) => x.contains(&addr),
+                    Some(_) => true,
+                    None => false,
+                };
+                if rejected || bound.contains_key(&addr) {
+                    return None;
+                }
+
+                (
+                    name,
+                    Candidate {
+                        addr,
+                        score: 0.5,
+                        anchor: None,
+                    },
+                )
+                    .as_some()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A diamond from `base`: a test at the entry, two arms and a join that returns. `arm_calls`
+    // is how many calls the first arm makes
+    fn diamond(base: u64, arm_calls: usize) -> Function {
+        let block = |offset: u64, branch: Branch, calls: usize| Block {
+            address: Address {
+                addr: base + offset,
+                block_addr: base + offset,
+                function_addr: base,
+            },
+            calls: (0..calls).map(|_| Dest::Unknown).collect(),
+            branch,
+            strings: Vec::new(),
+        };
+        let to = |offset: u64| Dest::Known(base + offset);
+
+        Function {
+            name: Some(format!("f{:x}", base)),
+            address: Address {
+                addr: base,
+                block_addr: base,
+                function_addr: base,
+            },
+            blocks: vec![
+                block(0, Branch::Equality(to(0x10), to(0x20)), 0),
+                block(0x10, Branch::Neutral(to(0x30)), arm_calls),
+                block(0x20, Branch::Neutral(to(0x30)), 0),
+                block(0x30, Branch::Return, 0),
+            ],
+            xrefs: Vec::new(),
+        }
+    }
+
+    // The same diamond with its arms laid out the other way round, so the arm making the calls
+    // is at +0x20
+    fn swapped_diamond(base: u64, arm_calls: usize) -> Function {
+        let mut func = diamond(base, arm_calls);
+        func.blocks[0].branch =
+            Branch::Equality(Dest::Known(base + 0x20), Dest::Known(base + 0x10));
+        let calls = std::mem::take(&mut func.blocks[1].calls);
+        func.blocks[2].calls = calls;
+        func
+    }
+
+    fn exec(fns: Vec<Function>) -> ExecDB {
+        ExecDB {
+            header: ExecHeader::default(),
+            meta: Metadata::default(),
+            fns: fns
+                .into_iter()
+                .map(|x| (x.address.function_addr, x))
+                .collect(),
+            vtables: HashMap::new(),
+            strings: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_addresses() {
+        assert!(fingerprint(&diamond(0x1000, 1)) == fingerprint(&diamond(0x8000, 1)));
+        assert!(fingerprint(&diamond(0x1000, 1)) != fingerprint(&diamond(0x1000, 2)));
+    }
+
+    #[test]
+    fn fingerprint_sees_branches_and_degrees() {
+        let mut func = diamond(0x1000, 1);
+        func.blocks[0].branch = Branch::Inequality(Dest::Known(0x1010), Dest::Known(0x1020));
+        assert!(fingerprint(&func) != fingerprint(&diamond(0x1000, 1)));
+
+        // The second arm skips the join, which changes the join's in degree
+        let mut func = diamond(0x1000, 1);
+        func.blocks[2].branch = Branch::Return;
+        assert!(fingerprint(&func) != fingerprint(&diamond(0x1000, 1)));
+    }
+
+    #[test]
+    fn arm_layout_changes_nothing() {
+        let (func, swapped) = (diamond(0x1000, 1), swapped_diamond(0x8000, 1));
+        assert!(fingerprint(&func) == fingerprint(&swapped));
+
+        let binds = BindDB {
+            binds: HashMap::new(),
+            evidence: HashMap::new(),
+            base: None,
+        };
+        let pair = ExecPair {
+            input: exec(Vec::new()),
+            output: exec(Vec::new()),
+        };
+        let alignment = align_functions(&binds, &pair, &HashMap::new(), &func, &swapped);
+
+        let offsets: Vec<(u64, u64)> = alignment
+            .blocks
+            .iter()
+            .map(|(x, y)| (x.address.block_addr - 0x1000, y.address.block_addr - 0x8000))
+            .collect();
+        assert_eq!(offsets, [(0, 0), (0x20, 0x10), (0x10, 0x20), (0x30, 0x30)]);
+        assert_eq!(alignment.similarity, 1.0);
+    }
+
+    #[test]
+    fn unique_fingerprints_drops_shared_and_small() {
+        let mut small = diamond(0x4000, 0);
+        small.blocks.truncate(2);
+
+        let exec = exec(vec![
+            diamond(0x1000, 1),
+            diamond(0x2000, 1),
+            diamond(0x3000, 2),
+            small,
+        ]);
+        let unique = unique_fingerprints(&exec);
+
+        assert_eq!(unique.len(), 1);
+        assert_eq!(
+            unique[&fingerprint(&diamond(0, 2))].address.function_addr,
+            0x3000
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use colored::Colorize;
+use std::collections::{HashMap, HashSet};
+
+use crate::db::*;
+use crate::util::*;
+
+// How many calls or strings to show per side before cutting the list short
+const MAX_LIST: usize = 12;
+
+// What a function looks like from the outside
+struct Summary {
+    title: String,
+    blocks: usize,
+    xrefs: usize,
+    calls: Vec<String>,
+    strings: Vec<String>,
+}
+
+impl Summary {
+    fn new(title: String, func: &Function, call_name: impl Fn(u64) -> String) -> Self {
+        let mut blocks: Vec<_> = func.blocks.iter().collect();
+        blocks.sort_by_key(|x| x.address.block_addr);
+
+        Summary {
+            title,
+            blocks: blocks.len(),
+            xrefs: func.xrefs.len(),
+            calls: blocks
+                .iter()
+                .flat_map(|x| &x.calls)
+                .map(|x| match x {
+                    Dest::Known(x) => call_name(*x),
+                    Dest::Unknown => String::from("<unknown>"),
+                })
+                .collect(),
+            strings: blocks
+                .iter()
+                .flat_map(|x| &x.strings)
+                .map(|x| format!("{:?}", x))
+                .collect(),
+        }
+    }
+}
+
+// A line of one side, and whether the other side agrees with it (if that means anything)
+type Cell = (String, Option<bool>);
+
+fn render(cell: &Cell, width: usize) -> String {
+    let mut text: String = cell.0.chars().take(width).collect();
+    let len = text.chars().count();
+    text.push_str(&" ".repeat(width - len));
+
+    match cell.1 {
+        Some(true) => text.green().to_string(),
+        Some(false) => text.red().to_string(),
+        None => text,
+    }
+}
+
+// Rows comparing a list on both sides, marking entries the other side doesn't have
+fn list_rows(label: &str, left: &[String], right: &[String]) -> Vec<(Cell, Cell)> {
+    let left_set: HashSet<_> = left.iter().collect();
+    let right_set: HashSet<_> = right.iter().collect();
+
+    let mut rows = vec![(
+        (format!("{} ({})", label, left.len()), None),
+        (format!("{} ({})", label, right.len()), None),
+    )];
+
+    let len = left.len().max(right.len());
+    for i in 0..len.min(MAX_LIST) {
+        let cell = |list: &[String], other: &HashSet<&String>| {
+            list.get(i)
+                .map(|x| (format!("  {}", x), Some(other.contains(x))))
+                .unwrap_or_default()
+        };
+        rows.push((cell(left, &right_set), cell(right, &left_set)));
+    }
+
+    if len > MAX_LIST {
+        let more = |list: &[String]| {
+            (
+                format!("  ... {} more", list.len().saturating_sub(MAX_LIST)),
+                None,
+            )
+        };
+        rows.push((more(left), more(right)));
+    }
+
+    rows
+}
+
+/// Lines showing the input function for a symbol next to the output function at an address,
+/// each side `width` characters wide
+pub fn comparison_lines(
+    pair: &ExecPair,
+    names: &HashMap<u64, String>,
+    sym: &str,
+    addr: u64,
+    width: usize,
+) -> Vec<String> {
+    let input = pair
+        .input
+        .fns
+        .values()
+        .find(|x| x.name.as_deref() == Some(sym))
+        .map(|x| {
+            Summary::new(
+                format!("Input {}", x.address.function_addr.as_hex()),
+                x,
+                |addr| {
+                    pair.input
+                        .fns
+                        .get(&addr)
+                        .and_then(|x| x.name.as_deref())
+                        .map(demangle)
+                        .unwrap_or(addr.as_hex())
+                },
+            )
+        });
+
+    let output = pair.output.fns.get(&addr).map(|x| {
+        Summary::new(
+            format!(
+                "Output {}{}",
+                addr.as_hex(),
+                names
+                    .get(&addr)
+                    .map(|x| format!(" (bound to {})", demangle(x)))
+                    .unwrap_or_default()
+            ),
+            x,
+            |addr| {
+                names
+                    .get(&addr)
+                    .map(|x| demangle(x))
+                    .or_else(|| pair.output.fns.get(&addr)?.name.as_deref().map(demangle))
+                    .unwrap_or(addr.as_hex())
+            },
+        )
+    });
+
+    let (input, output) = match (input, output) {
+        (Some(x), Some(y)) => (x, y),
+        (x, y) => {
+            let mut lines = Vec::new();
+            if x.is_none() {
+                lines.push("No input function to compare".red().to_string());
+            }
+            if y.is_none() {
+                lines.push("No output function to compare".red().to_string());
+            }
+            return lines;
+        }
+    };
+
+    let count = |label: &str, x: usize, y: usize| -> (Cell, Cell) {
+        (
+            (format!("{}: {}", label, x), Some(x == y)),
+            (format!("{}: {}", label, y), Some(x == y)),
+        )
+    };
+
+    let mut rows = vec![
+        count("Blocks", input.blocks, output.blocks),
+        count("Xrefs", input.xrefs, output.xrefs),
+    ];
+    rows.extend(list_rows("Calls", &input.calls, &output.calls));
+    rows.extend(list_rows("Strings", &input.strings, &output.strings));
+
+    let mut lines = vec![
+        format!(
+            "{} | {}",
+            render(&(input.title, None), width).bold(),
+            render(&(output.title, None), width).bold()
+        ),
+        "-".repeat(width * 2 + 3).dimmed().to_string(),
+    ];
+    lines.extend(
+        rows.into_iter()
+            .map(|(left, right)| format!("{} | {}", render(&left, width), render(&right, width))),
+    );
+    lines
+}
+
+/// Prints the input function for a symbol next to the output function at an address,
+/// so a conflict can be judged without opening a disassembler
+pub fn print_comparison(pair: &ExecPair, names: &HashMap<u64, String>, sym: &str, addr: u64) {
+    let width = crossterm::terminal::size()
+        .map(|(w, _)| w as usize)
+        .unwrap_or(120)
+        .saturating_sub(3)
+        / 2;
+
+    println!();
+    for line in comparison_lines(pair, names, sym, addr, width) {
+        println!("{}", line);
+    }
+    println!();
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+
+use crate::util::AsSome;
 
 // For Executable
 
@@ -24,6 +27,10 @@ pub enum Branch {
     // A if greater than B
     #[serde(rename = "I")]
     Inequality(Dest, Dest),
+    // A jump table, with its cases ordered by case value and then the default. The cases are
+    // left empty when the generator couldn't tell which target is which case
+    #[serde(rename = "S")]
+    Switch(Vec<Dest>, Dest),
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
@@ -83,8 +90,85 @@ pub struct Vtable {
     pub function_addrs: Vec<u64>,
 }
 
+/// Starts every versioned exdb. Files without it are a bare `ExecDB` from before versioning.
+pub const EXDB_MAGIC: &[u8; 4] = b"EXDB";
+/// Bumped whenever the layout of `ExecDB` changes, with a migration added to `ExecDB::load`
+pub const EXDB_VERSION: u32 = 2;
+
+/// What produced an exdb, stored in front of it
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExecHeader {
+    pub version: u32,
+    /// The backend that generated the database
+    pub backend: String,
+    pub arch: Option<String>,
+    /// Hex sha256 of the analyzed binary
+    pub hash: Option<String>,
+    /// Where the image was loaded for analysis
+    pub base: Option<u64>,
+    /// Seconds since the unix epoch
+    pub created: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename = "E")]
+pub enum Endian {
+    #[default]
+    #[serde(rename = "L")]
+    Little,
+    #[serde(rename = "B")]
+    Big,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename = "S")]
+pub struct SectionInfo {
+    #[serde(rename = "N")]
+    pub name: String,
+    #[serde(rename = "A")]
+    pub addr: u64,
+    #[serde(rename = "S")]
+    pub size: u64,
+    #[serde(rename = "R")]
+    pub read: bool,
+    #[serde(rename = "W")]
+    pub write: bool,
+    #[serde(rename = "X")]
+    pub exec: bool,
+}
+
+/// What the analyzed binary was built for and how it's laid out
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename = "M")]
+pub struct Metadata {
+    /// Like x86_64 or aarch64, empty if unknown
+    #[serde(rename = "A")]
+    pub arch: String,
+    #[serde(rename = "B")]
+    pub bits: u32,
+    #[serde(rename = "E")]
+    pub endian: Endian,
+    #[serde(rename = "I")]
+    pub base: u64,
+    #[serde(rename = "S")]
+    pub sections: Vec<SectionInfo>,
+}
+
+impl Metadata {
+    /// The section an address falls in
+    pub fn section(&self, addr: u64) -> Option<&SectionInfo> {
+        self.sections
+            .iter()
+            .find(|x| addr >= x.addr && addr < x.addr + x.size)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ExecDB {
+    #[serde(skip)]
+    pub header: ExecHeader,
+    #[serde(rename = "M", default)]
+    pub meta: Metadata,
     #[serde(rename = "F")]
     pub fns: HashMap<u64, Function>,
     #[serde(rename = "V")]
@@ -98,6 +182,25 @@ pub struct ExecPair {
     pub output: ExecDB,
 }
 
+impl ExecPair {
+    /// Whether symbols can be ported between the two, which needs them to share an architecture
+    pub fn check_compatible(&self) -> Result<(), String> {
+        let (input, output) = (&self.input.meta, &self.output.meta);
+        if input.arch.is_empty() || output.arch.is_empty() {
+            return Ok(());
+        }
+        // 0 bits is unknown, which older exdbs can be
+        let bits_differ = input.bits != 0 && output.bits != 0 && input.bits != output.bits;
+        if input.arch != output.arch || bits_differ {
+            return Err(format!(
+                "Input is {} ({} bit) but output is {} ({} bit)",
+                input.arch, input.bits, output.arch, output.bits
+            ));
+        }
+        Ok(())
+    }
+}
+
 // For Binds
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -108,12 +211,158 @@ pub enum Bind {
     Inline,
 }
 
+/// Why a symbol was proposed at an address
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Evidence {
+    pub addr: u64,
+    /// How sure the strategy was, from 0 to 1
+    pub score: f32,
+    pub strategy: String,
+    /// The bound symbol or string that led to the proposal
+    pub anchor: Option<String>,
+    pub round: usize,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct BindDB {
     pub binds: HashMap<String, Bind>,
+    #[serde(default)]
+    pub evidence: HashMap<String, Vec<Evidence>>,
+    /// Base of the output exdb the addresses were found in, if known
+    #[serde(default)]
+    pub base: Option<u64>,
+}
+
+impl Dest {
+    fn shift(&mut self, delta: u64) {
+        if let Dest::Known(x) = self {
+            *x = x.wrapping_add(delta);
+        }
+    }
+}
+
+impl Branch {
+    fn shift(&mut self, delta: u64) {
+        match self {
+            Branch::Return => {}
+            Branch::Neutral(x) => x.shift(delta),
+            Branch::Equality(x, y) | Branch::Inequality(x, y) => {
+                x.shift(delta);
+                y.shift(delta);
+            }
+            Branch::Switch(cases, default) => {
+                cases.iter_mut().for_each(|x| x.shift(delta));
+                default.shift(delta);
+            }
+        }
+    }
+}
+
+impl Address {
+    fn shift(&mut self, delta: u64) {
+        self.addr = self.addr.wrapping_add(delta);
+        self.block_addr = self.block_addr.wrapping_add(delta);
+        self.function_addr = self.function_addr.wrapping_add(delta);
+    }
+}
+
+/// The bitness an architecture name implies, or 0 if it doesn't. Names are given the way
+/// every backend gives them, with 64 bit x86 and ARM named apart from the 32 bit ones
+fn arch_bits(arch: &str) -> u32 {
+    match arch {
+        "x86_64" | "aarch64" => 64,
+        "x86" | "arm" => 32,
+        _ => 0,
+    }
 }
 
 impl ExecDB {
+    /// Reads an exdb, upgrading it if it was written by an older version
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = std::fs::read(path)?;
+
+        let Some(rest) = data.strip_prefix(EXDB_MAGIC) else {
+            let mut db: ExecDB = pot::from_slice(&data)?;
+            db.header.backend = String::from("unknown");
+            return Ok(db);
+        };
+
+        let version = u32::from_le_bytes(rest.get(..4).ok_or("Truncated exdb header")?.try_into()?);
+        let body = &rest[4..];
+
+        match version {
+            // Version 1 only knew the architecture and base, and only in the header
+            1 => {
+                let (header, mut db): (ExecHeader, ExecDB) = pot::from_slice(body)?;
+                db.meta.arch = header.arch.clone().unwrap_or_default();
+                db.meta.bits = arch_bits(&db.meta.arch);
+                db.meta.base = header.base.unwrap_or(0);
+                db.header = header;
+                Ok(db)
+            }
+            EXDB_VERSION => {
+                let (header, mut db): (ExecHeader, ExecDB) = pot::from_slice(body)?;
+                db.header = header;
+                Ok(db)
+            }
+            x => Err(format!(
+                "exdb version {} is newer than this build of symbo understands ({})",
+                x, EXDB_VERSION
+            )
+            .into()),
+        }
+    }
+
+    /// Writes the exdb with a header for the current version
+    pub fn save(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.header.version = EXDB_VERSION;
+        self.header.arch = (!self.meta.arch.is_empty()).then(|| self.meta.arch.clone());
+        self.header.base = Some(self.meta.base);
+
+        let mut data = EXDB_MAGIC.to_vec();
+        data.extend(EXDB_VERSION.to_le_bytes());
+        data.extend(pot::to_vec(&(&self.header, &*self))?);
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Moves every address to where it would be with the image loaded at `base`
+    pub fn rebase(&mut self, base: u64) {
+        let delta = base.wrapping_sub(self.meta.base);
+        if delta == 0 {
+            return;
+        }
+
+        self.fns = std::mem::take(&mut self.fns)
+            .into_iter()
+            .map(|(addr, mut func)| {
+                func.address.shift(delta);
+                func.xrefs.iter_mut().for_each(|x| x.shift(delta));
+                for block in &mut func.blocks {
+                    block.address.shift(delta);
+                    block.calls.iter_mut().for_each(|x| x.shift(delta));
+                    block.branch.shift(delta);
+                }
+                (addr.wrapping_add(delta), func)
+            })
+            .collect();
+
+        for vtable in self.vtables.values_mut() {
+            vtable.address = vtable.address.wrapping_add(delta);
+            // Pure virtual slots stay empty
+            for addr in vtable.function_addrs.iter_mut().filter(|x| **x != 0) {
+                *addr = addr.wrapping_add(delta);
+            }
+        }
+        for string in self.strings.values_mut() {
+            string.xrefs.iter_mut().for_each(|x| x.shift(delta));
+        }
+        for section in &mut self.meta.sections {
+            section.addr = section.addr.wrapping_add(delta);
+        }
+        self.meta.base = base;
+    }
+
     pub fn addr_to_block(&self, addr: &Address) -> Option<&Block> {
         self.fns
             .get(&addr.function_addr)?
@@ -131,4 +380,193 @@ impl Bind {
             _ => None,
         }
     }
+
+    /// Moves the addresses by `delta`
+    pub fn shift(&mut self, delta: u64) {
+        match self {
+            Bind::Verified(x) | Bind::Unverified(x) => *x = x.wrapping_add(delta),
+            Bind::Not(addrs) => addrs.iter_mut().for_each(|x| *x = x.wrapping_add(delta)),
+            Bind::Inline => {}
+        }
+    }
+}
+
+impl BindDB {
+    /// Moves every address from an image loaded at `from` to one loaded at `to`
+    pub fn rebase(&mut self, from: u64, to: u64) {
+        let delta = to.wrapping_sub(from);
+        self.binds.values_mut().for_each(|x| x.shift(delta));
+        for evidence in self.evidence.values_mut().flatten() {
+            evidence.addr = evidence.addr.wrapping_add(delta);
+        }
+        self.base = Some(to);
+    }
+
+    /// Rebases onto the output exdb's base if the symdb was made against another one,
+    /// giving back the base it was moved from. A base of 0 is unknown, so nothing is moved
+    /// unless both bases are known
+    pub fn follow_base(&mut self, base: u64) -> Option<u64> {
+        if base == 0 {
+            return None;
+        }
+        match self.base {
+            Some(old) if old != 0 && old != base => {
+                self.rebase(old, base);
+                Some(old)
+            }
+            _ => {
+                self.base = Some(base);
+                None
+            }
+        }
+    }
+
+    /// Records evidence for a symbol, keeping only the strongest from each strategy per address
+    pub fn add_evidence(&mut self, sym: &str, evidence: Evidence) {
+        let list = self.evidence.entry(sym.to_string()).or_default();
+        match list
+            .iter_mut()
+            .find(|x| x.addr == evidence.addr && x.strategy == evidence.strategy)
+        {
+            Some(x) if x.score < evidence.score => *x = evidence,
+            Some(_) => {}
+            None => list.push(evidence),
+        }
+    }
+
+    /// Bound symbols by the output address they're bound to
+    pub fn output_names(&self) -> HashMap<u64, String> {
+        self.binds
+            .iter()
+            .filter_map(|(k, v)| (v.get_addr()?, k.clone()).as_some())
+            .collect()
+    }
+
+    /// The strongest score for the address a symbol is currently bound to
+    pub fn confidence(&self, sym: &str) -> Option<f32> {
+        let addr = self.binds.get(sym)?.get_addr()?;
+        self.evidence
+            .get(sym)?
+            .iter()
+            .filter(|x| x.addr == addr)
+            .map(|x| x.score)
+            .reduce(f32::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind_db(base: Option<u64>) -> BindDB {
+        BindDB {
+            binds: HashMap::from([
+                ("a".to_string(), Bind::Verified(0x1010)),
+                ("b".to_string(), Bind::Not(vec![0x1020, 0x1030])),
+                ("c".to_string(), Bind::Inline),
+            ]),
+            evidence: HashMap::from([(
+                "a".to_string(),
+                vec![Evidence {
+                    addr: 0x1010,
+                    score: 1.0,
+                    strategy: "test".to_string(),
+                    anchor: None,
+                    round: 0,
+                }],
+            )]),
+            base,
+        }
+    }
+
+    fn exec_pair(input: (&str, u32), output: (&str, u32)) -> ExecPair {
+        let exec = |(arch, bits): (&str, u32)| ExecDB {
+            header: ExecHeader::default(),
+            meta: Metadata {
+                arch: arch.to_string(),
+                bits,
+                ..Default::default()
+            },
+            fns: HashMap::new(),
+            vtables: HashMap::new(),
+            strings: HashMap::new(),
+        };
+        ExecPair {
+            input: exec(input),
+            output: exec(output),
+        }
+    }
+
+    #[test]
+    fn compatibility_ignores_unknown_bits() {
+        assert!(exec_pair(("x86", 32), ("x86", 32))
+            .check_compatible()
+            .is_ok());
+        assert!(exec_pair(("x86", 0), ("x86", 32))
+            .check_compatible()
+            .is_ok());
+        assert!(exec_pair(("", 0), ("aarch64", 64))
+            .check_compatible()
+            .is_ok());
+        assert!(exec_pair(("x86", 32), ("x86", 64))
+            .check_compatible()
+            .is_err());
+        assert!(exec_pair(("x86_64", 64), ("aarch64", 64))
+            .check_compatible()
+            .is_err());
+    }
+
+    #[test]
+    fn arch_bits_from_name() {
+        assert_eq!(arch_bits("x86_64"), 64);
+        assert_eq!(arch_bits("arm"), 32);
+        assert_eq!(arch_bits("mips"), 0);
+    }
+
+    #[test]
+    fn rebase_moves_every_address() {
+        let mut db = bind_db(Some(0x1000));
+        db.rebase(0x1000, 0x5000);
+
+        assert_eq!(db.binds["a"], Bind::Verified(0x5010));
+        assert_eq!(db.binds["b"], Bind::Not(vec![0x5020, 0x5030]));
+        assert_eq!(db.binds["c"], Bind::Inline);
+        assert_eq!(db.evidence["a"][0].addr, 0x5010);
+        assert_eq!(db.base, Some(0x5000));
+
+        // Down as well as up
+        db.rebase(0x5000, 0x1000);
+        assert_eq!(db.binds["a"], Bind::Verified(0x1010));
+    }
+
+    #[test]
+    fn follow_base_rebases_between_known_bases() {
+        let mut db = bind_db(Some(0x1000));
+        assert_eq!(db.follow_base(0x2000), Some(0x1000));
+        assert_eq!(db.binds["a"], Bind::Verified(0x2010));
+
+        assert_eq!(db.follow_base(0x2000), None);
+        assert_eq!(db.binds["a"], Bind::Verified(0x2010));
+    }
+
+    #[test]
+    fn follow_base_ignores_unknown_bases() {
+        let mut db = bind_db(Some(0x1000));
+        assert_eq!(db.follow_base(0), None);
+        assert_eq!(db.binds["a"], Bind::Verified(0x1010));
+        assert_eq!(db.base, Some(0x1000));
+
+        let mut db = bind_db(None);
+        assert_eq!(db.follow_base(0), None);
+        assert_eq!(db.base, None);
+
+        // Only recorded once it's known
+        assert_eq!(db.follow_base(0x2000), None);
+        assert_eq!(db.binds["a"], Bind::Verified(0x1010));
+        assert_eq!(db.base, Some(0x2000));
+
+        let mut db = bind_db(Some(0));
+        assert_eq!(db.follow_base(0x2000), None);
+        assert_eq!(db.binds["a"], Bind::Verified(0x1010));
+    }
 }
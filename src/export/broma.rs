@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap};
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::db::*;
+use crate::util::*;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Platform {
+    Win,
+    Imac,
+    M1,
+    Ios,
+    Android32,
+    Android64,
+}
+
+impl Platform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Win => "win",
+            Platform::Imac => "imac",
+            Platform::M1 => "m1",
+            Platform::Ios => "ios",
+            Platform::Android32 => "android32",
+            Platform::Android64 => "android64",
+        }
+    }
+}
+
+/// A bound function, pulled apart into the pieces broma wants
+#[derive(Clone)]
+pub struct Method {
+    /// Fully qualified class name, or None for free functions
+    pub class: Option<String>,
+    pub name: String,
+    pub params: String,
+    /// Qualifiers after the parameters, like `const`
+    pub suffix: String,
+    pub virt: bool,
+    pub addr: u64,
+}
+
+impl Method {
+    fn is_structor(&self) -> bool {
+        self.class
+            .as_ref()
+            .and_then(|x| x.rsplit("::").next())
+            .is_some_and(|x| self.name == x || self.name.strip_prefix('~') == Some(x))
+    }
+
+    fn declaration(&self, platform: Platform) -> String {
+        format!(
+            "{}{}{}({}){} = {} {};",
+            if self.virt { "virtual " } else { "" },
+            // Mangled names don't say what they return
+            if self.is_structor() {
+                ""
+            } else {
+                "TodoReturn "
+            },
+            self.name,
+            self.params,
+            self.suffix,
+            platform.name(),
+            self.addr.as_hex()
+        )
+    }
+}
+
+// Finds the parenthesis closing the one at `open`
+fn closing_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Splits "ns::Class::method(params) const" into its scope, parameters and trailing qualifiers
+fn split_signature(demangled: &str) -> Option<(Vec<String>, String, String)> {
+    if demangled.contains("(anonymous namespace)") {
+        return None;
+    }
+
+    let open = match demangled.find("operator") {
+        // Operators can be made of <, > and (), so skip past the symbol before looking for parameters
+        Some(op) => {
+            let rest = &demangled[op + 8..];
+            let skip = if rest.starts_with("()") { 2 } else { 0 };
+            op + 8 + skip + rest[skip..].find('(')?
+        }
+        None => {
+            let mut depth = 0;
+            demangled
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '<' => depth += 1,
+                        '>' => depth -= 1,
+                        '(' => return depth == 0,
+                        _ => {}
+                    }
+                    false
+                })?
+                .0
+        }
+    };
+    let close = closing_paren(demangled, open)?;
+
+    let name = &demangled[..open];
+    let mut scope = Vec::new();
+    let mut depth = 0;
+    let mut last = 0;
+    for (i, c) in name.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            // Templated functions come with their return type, which broma can't take
+            ' ' if depth == 0 && !name[last..].starts_with("operator") => return None,
+            ':' if depth == 0 && name[i..].starts_with("::") => {
+                scope.push(name[last..i].to_string());
+                last = i + 2;
+            }
+            _ => {}
+        }
+    }
+    scope.push(name[last..].to_string());
+
+    Some((
+        scope,
+        demangled[open + 1..close].to_string(),
+        demangled[close + 1..].trim_end().to_string(),
+    ))
+}
+
+/// Every exported bind that broma can describe
+pub fn methods(binds: &[(&str, u64)], exec: &ExecDB) -> Vec<Method> {
+    let fns_by_name: HashMap<&str, u64> = exec
+        .fns
+        .values()
+        .filter_map(|x| (x.name.as_deref()?, x.address.function_addr).as_some())
+        .collect();
+
+    binds
+        .iter()
+        .filter_map(|&(sym, addr)| {
+            let demangled = cpp_demangle::Symbol::new(sym).ok()?.to_string();
+            let (mut scope, params, suffix) = split_signature(&demangled)?;
+            let name = scope.pop()?;
+            let class = (!scope.is_empty()).then(|| scope.join("::"));
+
+            let virt = class
+                .as_ref()
+                .and_then(|x| exec.vtables.get(x))
+                .zip(fns_by_name.get(sym))
+                .is_some_and(|(vtable, addr)| vtable.function_addrs.contains(addr));
+
+            Some(Method {
+                class,
+                name,
+                params,
+                suffix,
+                virt,
+                addr,
+            })
+        })
+        .collect()
+}
+
+/// Writes a fresh bindings file with one block per class
+pub fn generate(methods: &[Method], platform: Platform) -> String {
+    let mut classes: BTreeMap<Option<&str>, Vec<&Method>> = BTreeMap::new();
+    for method in methods {
+        classes
+            .entry(method.class.as_deref())
+            .or_default()
+            .push(method);
+    }
+
+    let mut out = String::new();
+    for (class, mut methods) in classes {
+        methods.sort_by(|a, b| (&a.name, &a.params).cmp(&(&b.name, &b.params)));
+
+        match class {
+            Some(class) => {
+                out += &format!("class {} {{\n", class);
+                for method in methods {
+                    out += &format!("\t{}\n", method.declaration(platform));
+                }
+                out += "}\n\n";
+            }
+            None => {
+                for method in methods {
+                    out += &format!("{}\n", method.declaration(platform));
+                }
+                out += "\n";
+            }
+        }
+    }
+
+    out
+}
+
+// The class a line opens, if it opens one
+fn class_name(line: &str) -> Option<String> {
+    let name = line
+        .strip_prefix("class ")?
+        .split(|x: char| x.is_whitespace() || x == '{')
+        .find(|x| !x.is_empty())?;
+    Some(name.trim_end_matches(':').to_string())
+}
+
+// The name and parameters of a declaration in an existing bindings file
+fn declared(line: &str) -> Option<(&str, String)> {
+    let open = line.find('(')?;
+    let close = closing_paren(line, open)?;
+    let name = line[..open].split_whitespace().last()?;
+    let params = line[open + 1..close]
+        .chars()
+        .filter(|x| !x.is_whitespace())
+        .collect();
+    Some((name, params))
+}
+
+// Adds or replaces the platform's address in a declaration
+fn set_address(line: &str, platform: Platform, addr: u64) -> String {
+    let binding = format!("{} {}", platform.name(), addr.as_hex());
+
+    let existing = Regex::new(&format!(r"\b{}\s+0x[0-9a-fA-F]+", platform.name())).unwrap();
+    if existing.is_match(line) {
+        return existing.replace(line, binding.as_str()).into_owned();
+    }
+
+    let (Some(semi), Some(close)) = (line.rfind(';'), line.rfind(')')) else {
+        return line.to_string();
+    };
+    let (head, tail) = line.split_at(semi);
+    if head[close..].contains('=') {
+        format!("{}, {}{}", head.trim_end(), binding, tail)
+    } else {
+        format!("{} = {}{}", head.trim_end(), binding, tail)
+    }
+}
+
+/// Puts the addresses into an existing bindings file, adding whatever it doesn't declare yet
+pub fn merge(existing: &str, methods: &[Method], platform: Platform) -> String {
+    let mut used = vec![false; methods.len()];
+    let mut out = String::new();
+    let mut class: Option<String> = None;
+    let mut depth = 0;
+
+    for line in existing.lines() {
+        let trimmed = line.trim();
+        let mut line = line.to_string();
+
+        if depth == 0 {
+            class = class_name(trimmed);
+        }
+
+        // Members sit right inside a class, free functions outside of any
+        let member = (depth == 1 && class.is_some()) || (depth == 0 && class.is_none());
+        if member && !trimmed.contains('{') {
+            if let Some((name, params)) = declared(trimmed) {
+                let candidates: Vec<_> = methods
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, x)| !used[*i] && x.class == class && x.name == name)
+                    .collect();
+                let exact = candidates.iter().find(|(_, x)| {
+                    x.params
+                        .chars()
+                        .filter(|x| !x.is_whitespace())
+                        .collect::<String>()
+                        == params
+                });
+
+                if let Some((i, method)) =
+                    exact.or(candidates.first().filter(|_| candidates.len() == 1))
+                {
+                    line = set_address(&line, platform, method.addr);
+                    used[*i] = true;
+                }
+            }
+        }
+
+        // Anything the class doesn't declare yet goes at the end of it
+        if depth == 1 && class.is_some() && trimmed.starts_with('}') {
+            for (i, method) in methods.iter().enumerate() {
+                if !used[i] && method.class == class {
+                    out += &format!("\t{}\n", method.declaration(platform));
+                    used[i] = true;
+                }
+            }
+        }
+
+        depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+        out += &line;
+        out += "\n";
+    }
+
+    let leftover: Vec<_> = methods
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !used[*i])
+        .map(|(_, x)| x.clone())
+        .collect();
+    if !leftover.is_empty() {
+        out += "\n";
+        out += &generate(&leftover, platform);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(scope: &[&str], params: &str, suffix: &str) -> Option<(Vec<String>, String, String)> {
+        Some((
+            scope.iter().map(|x| x.to_string()).collect(),
+            params.to_string(),
+            suffix.to_string(),
+        ))
+    }
+
+    #[test]
+    fn split_plain_methods() {
+        assert_eq!(
+            split_signature("cocos2d::CCNode::setPosition(cocos2d::CCPoint const&)"),
+            parts(
+                &["cocos2d", "CCNode", "setPosition"],
+                "cocos2d::CCPoint const&",
+                ""
+            )
+        );
+        assert_eq!(
+            split_signature("GameManager::getGameVariable(char const*) const"),
+            parts(&["GameManager", "getGameVariable"], "char const*", " const")
+        );
+        assert_eq!(
+            split_signature("free_function()"),
+            parts(&["free_function"], "", "")
+        );
+    }
+
+    #[test]
+    fn split_templates_and_operators() {
+        assert_eq!(
+            split_signature("std::vector<int, std::allocator<int> >::push_back(int const&)"),
+            parts(
+                &["std", "vector<int, std::allocator<int> >", "push_back"],
+                "int const&",
+                ""
+            )
+        );
+        assert_eq!(
+            split_signature("Foo::operator()(int)"),
+            parts(&["Foo", "operator()"], "int", "")
+        );
+        assert_eq!(
+            split_signature("Foo::operator<(Foo const&) const"),
+            parts(&["Foo", "operator<"], "Foo const&", " const")
+        );
+    }
+
+    #[test]
+    fn split_rejects_what_broma_cant_take() {
+        assert_eq!(split_signature("(anonymous namespace)::helper()"), None);
+        // Templated functions come with a return type
+        assert_eq!(split_signature("void Foo::bar<int>(int)"), None);
+        assert_eq!(split_signature("not a signature"), None);
+    }
+}
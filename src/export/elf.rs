@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use object::write::{Object, Symbol, SymbolSection};
+use object::{Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolKind, SymbolScope};
+
+use crate::db::*;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Machine {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+}
+
+impl Machine {
+    /// The machine for an architecture named the way exdb metadata names it
+    pub fn from_arch(arch: &str) -> Option<Self> {
+        match arch {
+            "x86" => Some(Machine::X86),
+            "x86_64" => Some(Machine::X86_64),
+            "arm" => Some(Machine::Arm),
+            "aarch64" => Some(Machine::Aarch64),
+            _ => None,
+        }
+    }
+
+    fn architecture(&self) -> Architecture {
+        match self {
+            Machine::X86 => Architecture::I386,
+            Machine::X86_64 => Architecture::X86_64,
+            Machine::Arm => Architecture::Arm,
+            Machine::Aarch64 => Architecture::Aarch64,
+        }
+    }
+}
+
+/// How many bytes each function spans, going by where the next one starts. Blocks don't
+/// record their length, so the last function's size is left at 0 for unknown
+pub fn sizes(exec: &ExecDB) -> HashMap<u64, u64> {
+    let mut starts: Vec<_> = exec.fns.keys().copied().collect();
+    starts.sort();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| (*addr, starts.get(i + 1).map(|x| x - addr).unwrap_or(0)))
+        .collect()
+}
+
+/// A relocatable ELF holding nothing but a symbol table, for gdb's add-symbol-file
+pub fn generate(
+    binds: &[(&str, u64)],
+    exec: &ExecDB,
+    machine: Machine,
+) -> object::write::Result<Vec<u8>> {
+    let sizes = sizes(exec);
+    let mut obj = Object::new(
+        BinaryFormat::Elf,
+        machine.architecture(),
+        Endianness::Little,
+    );
+
+    for (name, addr) in binds {
+        obj.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value: *addr,
+            size: sizes.get(addr).copied().unwrap_or(0),
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Absolute,
+            flags: SymbolFlags::None,
+        });
+    }
+
+    obj.write()
+}
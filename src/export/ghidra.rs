@@ -0,0 +1,60 @@
+use crate::util::*;
+
+/// A python string literal holding `text`, which means the same to Jython 2 and Python 3
+fn python_string(text: &str) -> String {
+    let mut out = String::from("u\"");
+    for x in text.chars() {
+        match x {
+            '\\' => out += "\\\\",
+            '"' => out += "\\\"",
+            ' '..='~' => out.push(x),
+            x if (x as u32) < 0x100 => out += &format!("\\x{:02x}", x as u32),
+            x if (x as u32) < 0x10000 => out += &format!("\\u{:04x}", x as u32),
+            x => out += &format!("\\U{:08x}", x as u32),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A Ghidra python script naming every function, creating functions Ghidra missed
+pub fn generate(binds: &[(&str, u64)]) -> String {
+    let mut out = String::from(
+        "# Names functions from a symdb exported by symbo\n\
+         #@category Symbo\n\
+         from ghidra.program.model.symbol import SourceType\n\
+         \n\
+         symbols = [\n",
+    );
+
+    for (name, addr) in binds {
+        out += &format!("    ({}, {}),\n", addr.as_hex(), python_string(name));
+    }
+
+    out += "]\n\
+            \n\
+            for addr, name in symbols:\n    \
+                address = toAddr(addr)\n    \
+                func = getFunctionAt(address)\n    \
+                if func is None:\n        \
+                    func = createFunction(address, name)\n    \
+                if func is None:\n        \
+                    print(\"Couldn't create function at %s\" % address)\n        \
+                    continue\n    \
+                func.setName(name, SourceType.IMPORTED)\n";
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn python_string_escapes() {
+        assert_eq!(python_string("?bar@Foo@@QAEXXZ"), r#"u"?bar@Foo@@QAEXXZ""#);
+        assert_eq!(python_string(r#"a"b\c"#), r#"u"a\"b\\c""#);
+        assert_eq!(python_string("a\nb"), r#"u"a\x0ab""#);
+        assert_eq!(python_string("é€𝄞"), r#"u"\xe9\u20ac\U0001d11e""#);
+    }
+}
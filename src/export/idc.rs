@@ -0,0 +1,51 @@
+use crate::util::*;
+
+/// An IDC string literal holding `text`. Anything outside printable ASCII goes in as octal
+/// bytes, which unlike hex escapes can't run into the characters after them
+fn idc_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for x in text.bytes() {
+        match x {
+            b'\\' => out += "\\\\",
+            b'"' => out += "\\\"",
+            b' '..=b'~' => out.push(x as char),
+            x => out += &format!("\\{:03o}", x),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// An IDA script naming every function, creating functions IDA missed
+pub fn generate(binds: &[(&str, u64)]) -> String {
+    let mut out = String::from(
+        "// Names functions from a symdb exported by symbo\n\
+         #include <idc.idc>\n\
+         \n\
+         static main() {\n",
+    );
+
+    for (name, addr) in binds {
+        out += &format!(
+            "    add_func({0});\n    set_name({0}, {1}, SN_NOWARN | SN_NOCHECK);\n",
+            addr.as_hex(),
+            idc_string(name)
+        );
+    }
+
+    out += "}\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idc_string_escapes() {
+        assert_eq!(idc_string("?bar@Foo@@QAEXXZ"), r#""?bar@Foo@@QAEXXZ""#);
+        assert_eq!(idc_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(idc_string("a\n1"), r#""a\0121""#);
+        assert_eq!(idc_string("é"), r#""\303\251""#);
+    }
+}
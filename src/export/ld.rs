@@ -0,0 +1,35 @@
+use crate::util::*;
+
+/// A GNU linker script defining every symbol at its address
+pub fn generate(binds: &[(&str, u64)]) -> String {
+    let mut out = String::from("/* Symbols from a symdb exported by symbo */\n");
+
+    for (name, addr) in binds {
+        // Quoted, since mangled names are full of characters the script would read as operators
+        out += &format!(
+            "\"{}\" = {};\n",
+            name.replace('\\', "\\\\").replace('"', "\\\""),
+            addr.as_hex()
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_quoted() {
+        let script = generate(&[("?bar@Foo@@QAEXXZ", 0x1000), (r#"a"b\c"#, 0x2000)]);
+        let lines: Vec<_> = script.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                format!(r#""?bar@Foo@@QAEXXZ" = {};"#, 0x1000u64.as_hex()),
+                format!(r#""a\"b\\c" = {};"#, 0x2000u64.as_hex()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+pub mod broma;
+pub mod elf;
+pub mod ghidra;
+pub mod idc;
+pub mod ld;
+pub mod rizin;
+
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use std::fs;
+
+use crate::db::*;
+
+#[derive(Clone, ValueEnum)]
+pub enum Format {
+    /// Geode bindings file, with methods grouped into classes
+    Broma,
+    /// Ghidra python script renaming functions
+    Ghidra,
+    /// Rizin script of afn and f commands
+    Rizin,
+    /// IDA script renaming functions
+    Idc,
+    /// ELF object with only a symbol table, for gdb's add-symbol-file
+    Elf,
+    /// GNU linker script of symbol assignments
+    Ld,
+}
+
+#[derive(Args)]
+pub struct Export {
+    /// The format to export to.
+    #[clap(short, long, value_enum)]
+    format: Format,
+    symdb: PathBuf,
+
+    /// The input exdb the symdb was made from
+    #[clap(long)]
+    from: Option<PathBuf>,
+    /// The output exdb the symdb was made for
+    #[clap(long)]
+    to: Option<PathBuf>,
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Export unverified symbols too
+    #[clap(short, long)]
+    unverified: bool,
+
+    /// Platform the addresses are for (broma only)
+    #[clap(long, value_enum, default_value_t = broma::Platform::Win)]
+    platform: broma::Platform,
+    /// Existing bindings file to merge the addresses into in place (broma only)
+    #[clap(long)]
+    merge: Option<PathBuf>,
+
+    /// Machine the ELF object is for, if the output exdb doesn't record one (elf only)
+    #[clap(long, value_enum)]
+    machine: Option<elf::Machine>,
+}
+
+fn load_exec(path: Option<PathBuf>, flag: &str) -> Result<ExecDB, Box<dyn std::error::Error>> {
+    let path = path.ok_or(format!("This format needs {}", flag))?;
+    ExecDB::load(&path)
+}
+
+/// The symbols worth exporting with their addresses, sorted by address
+pub fn exported(binds: &BindDB, unverified: bool) -> Vec<(&str, u64)> {
+    let mut out: Vec<_> = binds
+        .binds
+        .iter()
+        .filter_map(|(k, v)| match v {
+            Bind::Verified(x) => Some((k.as_str(), *x)),
+            Bind::Unverified(x) if unverified => Some((k.as_str(), *x)),
+            _ => None,
+        })
+        .collect();
+    out.sort_by_key(|x| (x.1, x.0));
+    out
+}
+
+impl Export {
+    pub fn export(self) -> Result<(), Box<dyn std::error::Error>> {
+        let binds: BindDB = serde_json::from_slice(&fs::read(&self.symdb)?)?;
+        let exported = exported(&binds, self.unverified);
+
+        let (name, data) = match self.format {
+            Format::Broma => {
+                let exec = load_exec(self.from, "--from")?;
+                let methods = broma::methods(&exported, &exec);
+
+                if let Some(path) = self.merge {
+                    let merged = broma::merge(&fs::read_to_string(&path)?, &methods, self.platform);
+                    fs::write(self.output.unwrap_or(path), merged)?;
+                } else {
+                    fs::write(
+                        self.output.unwrap_or(PathBuf::from("bindings.bro")),
+                        broma::generate(&methods, self.platform),
+                    )?;
+                }
+                return Ok(());
+            }
+            Format::Ghidra => ("symbols.py", ghidra::generate(&exported).into_bytes()),
+            Format::Rizin => ("symbols.rz", rizin::generate(&exported).into_bytes()),
+            Format::Idc => ("symbols.idc", idc::generate(&exported).into_bytes()),
+            Format::Elf => {
+                let exec = load_exec(self.to, "--to")?;
+                let machine = self
+                    .machine
+                    .or(elf::Machine::from_arch(&exec.meta.arch))
+                    .ok_or("The output exdb doesn't record its architecture, pass --machine")?;
+                ("symbols.o", elf::generate(&exported, &exec, machine)?)
+            }
+            Format::Ld => ("symbols.ld", ld::generate(&exported).into_bytes()),
+        };
+
+        fs::write(self.output.unwrap_or(PathBuf::from(name)), data)?;
+        Ok(())
+    }
+}
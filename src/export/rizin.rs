@@ -0,0 +1,45 @@
+use crate::util::*;
+
+/// A name rizin takes as a single flag or function name. Anything it would read as part of
+/// the command, like `@`, `;` or whitespace, becomes an underscore
+fn flag_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|x| match x {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | ':' => x,
+            _ => '_',
+        })
+        .collect();
+    // Which would be read as a number
+    if out.starts_with(|x: char| x.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+/// A rizin script naming every function and flagging its address
+pub fn generate(binds: &[(&str, u64)]) -> String {
+    let mut out = String::from("# Names functions from a symdb exported by symbo\n");
+
+    for (name, addr) in binds {
+        let name = flag_name(name);
+        out += &format!("af @ {}\n", addr.as_hex());
+        out += &format!("afn {} @ {}\n", name, addr.as_hex());
+        out += &format!("f symbo.{} @ {}\n", name, addr.as_hex());
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_name_keeps_commands_out() {
+        assert_eq!(flag_name("_ZN3Foo3barEv"), "_ZN3Foo3barEv");
+        assert_eq!(flag_name("?bar@Foo@@QAEXXZ"), "_bar_Foo__QAEXXZ");
+        assert_eq!(flag_name("a; rm -rf /"), "a__rm__rf__");
+        assert_eq!(flag_name("3dmodel"), "_3dmodel");
+    }
+}
@@ -0,0 +1,153 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use gimli::{AttributeValue, EndianSlice, RunTimeEndian};
+use object::{Object, ObjectSection};
+use pdb::FallibleIterator;
+
+use crate::db::ExecDB;
+
+/// Function names from a PDB or from a binary carrying DWARF, by address.
+/// PDBs only know relative addresses, so they're placed at `base`.
+pub fn names(path: &Path, base: u64) -> Result<HashMap<u64, String>, Box<dyn std::error::Error>> {
+    let data = std::fs::read(path)?;
+    if data.starts_with(b"Microsoft C/C++ MSF 7.00") {
+        pdb_names(&data, base)
+    } else {
+        dwarf_names(&data)
+    }
+}
+
+/// Names every unnamed function the debug info knows about
+pub fn apply(exec: &mut ExecDB, names: HashMap<u64, String>) -> usize {
+    let mut count = 0;
+    for (addr, name) in names {
+        if let Some(func) = exec.fns.get_mut(&addr) {
+            if func.name.is_none() {
+                func.name = Some(name);
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn pdb_names(data: &[u8], base: u64) -> Result<HashMap<u64, String>, Box<dyn std::error::Error>> {
+    let mut pdb = pdb::PDB::open(std::io::Cursor::new(data))?;
+    let address_map = pdb.address_map()?;
+    let mut names = HashMap::new();
+
+    // Procedures have undecorated names, so the publics go in after them to win
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    while let Some(module) = modules.next()? {
+        let Some(info) = pdb.module_info(&module)? else {
+            continue;
+        };
+        let mut symbols = info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            if let Ok(pdb::SymbolData::Procedure(proc)) = symbol.parse() {
+                if let Some(rva) = proc.offset.to_rva(&address_map) {
+                    names.insert(base + rva.0 as u64, proc.name.to_string().into_owned());
+                }
+            }
+        }
+    }
+
+    let globals = pdb.global_symbols()?;
+    let mut symbols = globals.iter();
+    while let Some(symbol) = symbols.next()? {
+        if let Ok(pdb::SymbolData::Public(public)) = symbol.parse() {
+            if !public.function {
+                continue;
+            }
+            if let Some(rva) = public.offset.to_rva(&address_map) {
+                names.insert(base + rva.0 as u64, public.name.to_string().into_owned());
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+fn dwarf_names(data: &[u8]) -> Result<HashMap<u64, String>, Box<dyn std::error::Error>> {
+    let file = object::File::parse(data)?;
+    let endian = if file.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+
+    let sections = gimli::DwarfSections::load(|id| -> Result<Cow<[u8]>, gimli::Error> {
+        Ok(file
+            .section_by_name(id.name())
+            .and_then(|x| x.uncompressed_data().ok())
+            .unwrap_or_default())
+    })?;
+    let dwarf = sections.borrow(|x| EndianSlice::new(x, endian));
+
+    let mut names = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            let Some(low_pc) = entry.attr_value(gimli::DW_AT_low_pc)? else {
+                continue;
+            };
+            let Some(addr) = dwarf.attr_address(&unit, low_pc)? else {
+                continue;
+            };
+
+            // Out of line copies keep their names on the declaration they point back to
+            let mut entry = entry.clone();
+            let mut name = None;
+            for _ in 0..3 {
+                for attr in [
+                    gimli::DW_AT_linkage_name,
+                    gimli::DW_AT_MIPS_linkage_name,
+                    gimli::DW_AT_name,
+                ] {
+                    if name.is_some() {
+                        break;
+                    }
+                    if let Some(value) = entry.attr_value(attr)? {
+                        name = Some(
+                            dwarf
+                                .attr_string(&unit, value)?
+                                .to_string_lossy()
+                                .into_owned(),
+                        );
+                    }
+                }
+                if name.as_ref().is_some_and(|x| x.starts_with("_Z")) {
+                    break;
+                }
+
+                let origin = match entry.attr_value(gimli::DW_AT_specification)? {
+                    Some(x) => Some(x),
+                    None => entry.attr_value(gimli::DW_AT_abstract_origin)?,
+                };
+                let Some(AttributeValue::UnitRef(offset)) = origin else {
+                    break;
+                };
+                entry = unit.entry(offset)?;
+                // A mangled name further along beats the plain one found so far
+                if entry.attr_value(gimli::DW_AT_linkage_name)?.is_some() {
+                    name = None;
+                }
+            }
+
+            if let Some(name) = name {
+                names.insert(addr, name);
+            }
+        }
+    }
+
+    Ok(names)
+}
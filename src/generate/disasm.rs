@@ -0,0 +1,407 @@
+use clap::ValueEnum;
+use iced_x86::{ConditionCode, DecoderOptions, FlowControl, Mnemonic, OpKind};
+use object::Architecture;
+
+use crate::db::{Branch, Dest};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Arch {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+        }
+    }
+
+    pub fn from_object(arch: Architecture) -> Result<Self, String> {
+        match arch {
+            Architecture::X86_64 => Ok(Arch::X86_64),
+            Architecture::Aarch64 => Ok(Arch::Aarch64),
+            x => Err(format!("Unsupported architecture: {:?}", x)),
+        }
+    }
+}
+
+/// When a conditional branch is taken, in the terms `Branch` cares about
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cond {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Cond {
+    /// Reads an ARM or x86 condition code, like the `ne` in `b.ne` or the `ae` in `jae`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "eq" | "e" | "z" | "pl" | "ns" | "vs" | "o" | "p" | "pe" => Some(Cond::Equal),
+            "ne" | "nz" | "mi" | "s" | "vc" | "no" | "np" | "po" => Some(Cond::NotEqual),
+            "lo" | "cc" | "ls" | "lt" | "le" | "b" | "c" | "nae" | "be" | "na" | "l" | "nge"
+            | "ng" => Some(Cond::Less),
+            "hs" | "cs" | "hi" | "ge" | "gt" | "a" | "nbe" | "ae" | "nb" | "nc" | "g" | "nle"
+            | "nl" => Some(Cond::Greater),
+            _ => None,
+        }
+    }
+
+    /// The branch for a block ending in this condition
+    pub fn branch(self, jump: Dest, fail: Dest) -> Branch {
+        match self {
+            Cond::Equal => Branch::Equality(jump, fail),
+            Cond::NotEqual => Branch::Equality(fail, jump),
+            Cond::Less => Branch::Inequality(fail, jump),
+            Cond::Greater => Branch::Inequality(jump, fail),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    Next,
+    Call(Option<u64>),
+    Jump(Option<u64>),
+    Cond(Cond, u64),
+    Return,
+    /// Traps and anything else execution doesn't come back from
+    Stop,
+}
+
+#[derive(Clone, Debug)]
+pub struct Insn {
+    pub len: u64,
+    pub flow: Flow,
+    /// Addresses of data the instruction points at
+    pub refs: Vec<u64>,
+}
+
+pub trait Decoder {
+    /// Decodes the instruction at the start of `bytes`, which sits at `addr`
+    fn decode(&mut self, addr: u64, bytes: &[u8]) -> Option<Insn>;
+}
+
+pub fn decoder(arch: Arch) -> Box<dyn Decoder> {
+    match arch {
+        Arch::X86_64 => Box::new(X86),
+        Arch::Aarch64 => Box::new(Arm64::default()),
+    }
+}
+
+pub struct X86;
+
+fn x86_cond(cond: ConditionCode) -> Cond {
+    match cond {
+        ConditionCode::b | ConditionCode::be | ConditionCode::l | ConditionCode::le => Cond::Less,
+        ConditionCode::a | ConditionCode::ae | ConditionCode::g | ConditionCode::ge => {
+            Cond::Greater
+        }
+        ConditionCode::ne | ConditionCode::np | ConditionCode::no | ConditionCode::s => {
+            Cond::NotEqual
+        }
+        _ => Cond::Equal,
+    }
+}
+
+impl Decoder for X86 {
+    fn decode(&mut self, addr: u64, bytes: &[u8]) -> Option<Insn> {
+        let mut decoder = iced_x86::Decoder::with_ip(64, bytes, addr, DecoderOptions::NONE);
+        if !decoder.can_decode() {
+            return None;
+        }
+        let insn = decoder.decode();
+        if insn.is_invalid() {
+            return None;
+        }
+
+        let target = || {
+            matches!(
+                insn.op0_kind(),
+                OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64
+            )
+            .then(|| insn.near_branch_target())
+        };
+
+        let flow = match insn.flow_control() {
+            FlowControl::Return => Flow::Return,
+            FlowControl::Call => Flow::Call(target()),
+            FlowControl::IndirectCall => Flow::Call(None),
+            FlowControl::UnconditionalBranch => Flow::Jump(target()),
+            FlowControl::IndirectBranch => Flow::Jump(None),
+            FlowControl::ConditionalBranch => {
+                let cond = if insn.is_jcx_short() {
+                    // Taken once the counter hits zero
+                    Cond::Equal
+                } else if insn.is_loop() || insn.is_loopcc() {
+                    Cond::NotEqual
+                } else {
+                    x86_cond(insn.condition_code())
+                };
+                Flow::Cond(cond, insn.near_branch_target())
+            }
+            FlowControl::Exception => Flow::Stop,
+            FlowControl::Interrupt if insn.mnemonic() == Mnemonic::Int3 => Flow::Stop,
+            _ => Flow::Next,
+        };
+
+        let refs = (0..insn.op_count())
+            .filter_map(|i| match insn.op_kind(i) {
+                OpKind::Memory if insn.is_ip_rel_memory_operand() => {
+                    Some(insn.ip_rel_memory_address())
+                }
+                OpKind::Immediate32 | OpKind::Immediate64 | OpKind::Immediate32to64 => {
+                    Some(insn.immediate(i))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Some(Insn {
+            len: insn.len() as u64,
+            flow,
+            refs,
+        })
+    }
+}
+
+/// Decodes just enough AArch64 to find branches and the data adrp pairs point at
+#[derive(Default)]
+pub struct Arm64 {
+    /// The page each register was last given by adrp
+    pages: [Option<u64>; 32],
+}
+
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((value as i64) << shift) >> shift
+}
+
+fn offset(addr: u64, value: u32, bits: u32, scale: i64) -> u64 {
+    addr.wrapping_add_signed(sign_extend(value, bits) * scale)
+}
+
+fn arm_cond(cond: u32) -> Cond {
+    match cond {
+        // eq, pl, vs
+        0b0000 | 0b0101 | 0b0110 => Cond::Equal,
+        // cs, hi, ge, gt
+        0b0010 | 0b1000 | 0b1010 | 0b1100 => Cond::Greater,
+        // cc, ls, lt, le
+        0b0011 | 0b1001 | 0b1011 | 0b1101 => Cond::Less,
+        // ne, mi, vc
+        _ => Cond::NotEqual,
+    }
+}
+
+impl Decoder for Arm64 {
+    fn decode(&mut self, addr: u64, bytes: &[u8]) -> Option<Insn> {
+        let word = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?);
+        let rd = (word & 0x1F) as usize;
+        let rn = ((word >> 5) & 0x1F) as usize;
+        let imm19 = (word >> 5) & 0x7FFFF;
+
+        let mut refs = Vec::new();
+        let flow = if word & 0xFFFF0000 == 0 || word & 0xFFE0001F == 0xD4200000 {
+            // udf and brk
+            Flow::Stop
+        } else if word & 0xFC000000 == 0x14000000 {
+            Flow::Jump(Some(offset(addr, word & 0x3FFFFFF, 26, 4)))
+        } else if word & 0xFC000000 == 0x94000000 {
+            Flow::Call(Some(offset(addr, word & 0x3FFFFFF, 26, 4)))
+        } else if word & 0xFF000010 == 0x54000000 {
+            match word & 0xF {
+                // b.al and b.nv always branch
+                0b1110 | 0b1111 => Flow::Jump(Some(offset(addr, imm19, 19, 4))),
+                cond => Flow::Cond(arm_cond(cond), offset(addr, imm19, 19, 4)),
+            }
+        } else if word & 0x7E000000 == 0x34000000 {
+            // cbz and cbnz
+            let cond = if word & (1 << 24) == 0 {
+                Cond::Equal
+            } else {
+                Cond::NotEqual
+            };
+            Flow::Cond(cond, offset(addr, imm19, 19, 4))
+        } else if word & 0x7E000000 == 0x36000000 {
+            // tbz and tbnz
+            let cond = if word & (1 << 24) == 0 {
+                Cond::Equal
+            } else {
+                Cond::NotEqual
+            };
+            Flow::Cond(cond, offset(addr, (word >> 5) & 0x3FFF, 14, 4))
+        } else if word & 0xFE000000 == 0xD6000000 {
+            // Branches to registers, including the pointer authenticating ones
+            match (word >> 21) & 0xF {
+                0b0000 | 0b1000 => Flow::Jump(None),
+                0b0001 | 0b1001 => Flow::Call(None),
+                _ => Flow::Return,
+            }
+        } else {
+            if word & 0x9F000000 == 0x90000000 {
+                // adrp
+                let imm = ((word >> 5) & 0x7FFFF) << 2 | (word >> 29) & 0x3;
+                self.pages[rd] = Some(offset(addr & !0xFFF, imm, 21, 0x1000));
+                return Some(Insn {
+                    len: 4,
+                    flow: Flow::Next,
+                    refs,
+                });
+            } else if word & 0x9F000000 == 0x10000000 {
+                // adr
+                let imm = ((word >> 5) & 0x7FFFF) << 2 | (word >> 29) & 0x3;
+                refs.push(offset(addr, imm, 21, 1));
+            } else if word & 0xFF800000 == 0x91000000 {
+                // add with an immediate, 64 bit
+                let shift = if word & (1 << 22) == 0 { 0 } else { 12 };
+                if let Some(page) = self.pages[rn] {
+                    refs.push(page + ((((word >> 10) & 0xFFF) as u64) << shift));
+                }
+            } else if word & 0xBFC00000 == 0xB9400000 {
+                // ldr with an unsigned offset, 32 and 64 bit
+                let scale = if word & (1 << 30) == 0 { 4 } else { 8 };
+                if let Some(page) = self.pages[rn] {
+                    refs.push(page + ((word >> 10) & 0xFFF) as u64 * scale);
+                }
+            } else if word & 0xBF000000 == 0x18000000 {
+                // ldr literal
+                refs.push(offset(addr, imm19, 19, 4));
+            }
+            self.pages[rd] = None;
+            Flow::Next
+        };
+
+        Some(Insn { len: 4, flow, refs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cond_from_name() {
+        assert_eq!(Cond::from_name("eq"), Some(Cond::Equal));
+        assert_eq!(Cond::from_name("nz"), Some(Cond::NotEqual));
+        assert_eq!(Cond::from_name("lo"), Some(Cond::Less));
+        assert_eq!(Cond::from_name("ge"), Some(Cond::Greater));
+        assert_eq!(Cond::from_name("nae"), Some(Cond::Less));
+        assert_eq!(Cond::from_name("al"), None);
+        assert_eq!(Cond::from_name(""), None);
+    }
+
+    #[test]
+    fn inverse_conds_give_the_same_branch() {
+        let pairs = [
+            ("eq", "ne"),
+            ("z", "nz"),
+            ("lt", "ge"),
+            ("le", "gt"),
+            ("lo", "hs"),
+            ("ls", "hi"),
+            ("b", "ae"),
+        ];
+        for (x, y) in pairs {
+            let x = Cond::from_name(x).unwrap();
+            let y = Cond::from_name(y).unwrap();
+            // Jumping to one place on x is falling through to it on the inverse
+            assert_eq!(
+                x.branch(Dest::Known(0x10), Dest::Known(0x20)),
+                y.branch(Dest::Known(0x20), Dest::Known(0x10))
+            );
+        }
+    }
+
+    #[test]
+    fn cond_branch_orientation() {
+        let branch = |cond: Cond| cond.branch(Dest::Known(0x10), Dest::Known(0x20));
+        assert_eq!(
+            branch(Cond::Equal),
+            Branch::Equality(Dest::Known(0x10), Dest::Known(0x20))
+        );
+        assert_eq!(
+            branch(Cond::Greater),
+            Branch::Inequality(Dest::Known(0x10), Dest::Known(0x20))
+        );
+        assert_eq!(
+            branch(Cond::Less),
+            Branch::Inequality(Dest::Known(0x20), Dest::Known(0x10))
+        );
+    }
+
+    fn arm64(arm: &mut Arm64, addr: u64, word: u32) -> (Flow, Vec<u64>) {
+        let insn = arm.decode(addr, &word.to_le_bytes()).unwrap();
+        assert_eq!(insn.len, 4);
+        (insn.flow, insn.refs)
+    }
+
+    #[test]
+    fn arm64_branches() {
+        let mut arm = Arm64::default();
+        let mut flow = |word| arm64(&mut arm, 0x1000, word).0;
+
+        assert_eq!(flow(0x14000004), Flow::Jump(Some(0x1010)));
+        // Backwards
+        assert_eq!(flow(0x17FFFFFF), Flow::Jump(Some(0xFFC)));
+        assert_eq!(flow(0x94000040), Flow::Call(Some(0x1100)));
+        assert_eq!(flow(0x54000040), Flow::Cond(Cond::Equal, 0x1008));
+        assert_eq!(flow(0x54000041), Flow::Cond(Cond::NotEqual, 0x1008));
+        assert_eq!(flow(0x5400004B), Flow::Cond(Cond::Less, 0x1008));
+        assert_eq!(flow(0x5400004C), Flow::Cond(Cond::Greater, 0x1008));
+        // b.al
+        assert_eq!(flow(0x5400004E), Flow::Jump(Some(0x1008)));
+        // cbz, cbnz, tbz and tbnz
+        assert_eq!(flow(0xB4000040), Flow::Cond(Cond::Equal, 0x1008));
+        assert_eq!(flow(0xB5000040), Flow::Cond(Cond::NotEqual, 0x1008));
+        assert_eq!(flow(0x36000040), Flow::Cond(Cond::Equal, 0x1008));
+        assert_eq!(flow(0x37000040), Flow::Cond(Cond::NotEqual, 0x1008));
+        // ret, br and blr
+        assert_eq!(flow(0xD65F03C0), Flow::Return);
+        assert_eq!(flow(0xD61F0200), Flow::Jump(None));
+        assert_eq!(flow(0xD63F0100), Flow::Call(None));
+        // brk, udf and nop
+        assert_eq!(flow(0xD4200000), Flow::Stop);
+        assert_eq!(flow(0x00000000), Flow::Stop);
+        assert_eq!(flow(0xD503201F), Flow::Next);
+    }
+
+    #[test]
+    fn arm64_data_refs() {
+        let mut arm = Arm64::default();
+
+        // adrp x0 then add x0, x0, #0x10
+        assert_eq!(arm64(&mut arm, 0x1000, 0xB0000000), (Flow::Next, vec![]));
+        assert_eq!(
+            arm64(&mut arm, 0x1004, 0x91004000),
+            (Flow::Next, vec![0x2010])
+        );
+        // x0 was overwritten by the add
+        assert_eq!(arm64(&mut arm, 0x1008, 0x91004000), (Flow::Next, vec![]));
+
+        // adrp x2 then ldr x1, [x2, #8]
+        arm64(&mut arm, 0x1000, 0xB0000002);
+        assert_eq!(
+            arm64(&mut arm, 0x1004, 0xF9400441),
+            (Flow::Next, vec![0x2008])
+        );
+
+        // adr x0, #0x10 and ldr x0, #8
+        assert_eq!(
+            arm64(&mut arm, 0x1000, 0x10000080),
+            (Flow::Next, vec![0x1010])
+        );
+        assert_eq!(
+            arm64(&mut arm, 0x1000, 0x58000040),
+            (Flow::Next, vec![0x1008])
+        );
+    }
+
+    #[test]
+    fn arm64_needs_a_whole_word() {
+        assert!(Arm64::default().decode(0x1000, &[0x1F, 0x20]).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use gimli::{BaseAddresses, CieOrFde, EhFrame, LittleEndian, UnwindSection};
+use object::{
+    Architecture, BinaryFormat, Object, ObjectSection, ObjectSegment, ObjectSymbol,
+    ObjectSymbolTable, RelocationFlags, RelocationTarget, SymbolKind,
+};
+
+use super::disasm::Arch;
+use super::image::{c_strings, type_name, Image};
+use crate::db::Vtable;
+use crate::util::*;
+
+// Function bounds from the unwind info, which even stripped binaries keep
+fn eh_frame_bounds(file: &object::File, image: &mut Image) -> Result<(), gimli::Error> {
+    let Some(section) = file.section_by_name(".eh_frame") else {
+        return Ok(());
+    };
+    let data = section.data().unwrap_or_default();
+    let eh_frame = EhFrame::new(data, LittleEndian);
+    let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+    if let Some(text) = file.section_by_name(".text") {
+        bases = bases.set_text(text.address());
+    }
+
+    let mut entries = eh_frame.entries(&bases);
+    while let Some(entry) = entries.next()? {
+        if let CieOrFde::Fde(partial) = entry {
+            let fde = partial.parse(EhFrame::cie_from_offset)?;
+            image.add_function(fde.initial_address(), Some(fde.end_address()));
+        }
+    }
+    Ok(())
+}
+
+// What each dynamically relocated pointer ends up as, or None for imports
+fn relocated_pointers(file: &object::File) -> HashMap<u64, Option<u64>> {
+    let relative = match file.architecture() {
+        Architecture::Aarch64 => object::elf::R_AARCH64_RELATIVE,
+        _ => object::elf::R_X86_64_RELATIVE,
+    };
+
+    let dynsym = file.dynamic_symbol_table();
+
+    file.dynamic_relocations()
+        .into_iter()
+        .flatten()
+        .map(|(offset, reloc)| {
+            let value = match (reloc.flags(), reloc.target()) {
+                (RelocationFlags::Elf { r_type }, _) if r_type == relative => {
+                    Some(reloc.addend() as u64)
+                }
+                (_, RelocationTarget::Symbol(index)) => dynsym
+                    .as_ref()
+                    .and_then(|x| x.symbol_by_index(index).ok())
+                    .filter(|x| x.is_definition())
+                    .map(|x| x.address().wrapping_add_signed(reloc.addend())),
+                _ => None,
+            };
+            (offset, value)
+        })
+        .collect()
+}
+
+// Vtables the symbol table still names
+fn vtables(file: &object::File, image: &Image) -> HashMap<String, Vtable> {
+    let pointers = relocated_pointers(file);
+    let pointer = |slot: u64| match pointers.get(&slot) {
+        Some(x) => Some(x.unwrap_or(0)),
+        None => image.read_u64(slot),
+    };
+
+    let symbols: Vec<_> = file
+        .symbols()
+        .chain(file.dynamic_symbols())
+        .filter(|x| x.is_definition() && x.kind() == SymbolKind::Data)
+        .collect();
+    let typeinfos: HashSet<u64> = symbols
+        .iter()
+        .filter(|x| x.name().is_ok_and(|x| x.starts_with("_ZTI")))
+        .map(|x| x.address())
+        .collect();
+
+    symbols
+        .iter()
+        .filter(|x| x.name().is_ok_and(|x| x.starts_with("_ZTV")))
+        .filter_map(|sym| {
+            let class = type_name(sym.name().ok()?.strip_prefix("_ZTV")?)?;
+
+            // The functions start after the typeinfo, which comes after one offset to top
+            // and any virtual base offsets
+            let slots: Vec<_> = (0..sym.size() / 8).map(|i| sym.address() + i * 8).collect();
+            let header = slots
+                .iter()
+                .take(4)
+                .position(|x| pointer(*x).is_some_and(|x| typeinfos.contains(&x)))
+                .unwrap_or(1)
+                + 1;
+
+            let function_addrs = slots
+                .iter()
+                .skip(header)
+                .map_while(|x| pointer(*x).filter(|x| *x == 0 || image.is_exec(*x)))
+                .collect();
+
+            (
+                class.clone(),
+                Vtable {
+                    name: class,
+                    address: sym.address() + header as u64 * 8,
+                    function_addrs,
+                },
+            )
+                .as_some()
+        })
+        .collect()
+}
+
+pub fn generate(path: PathBuf) -> Result<crate::db::ExecDB, Box<dyn std::error::Error>> {
+    println!("Initializing");
+
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data)?;
+    if file.format() != BinaryFormat::Elf {
+        return Err("Not an ELF file".into());
+    }
+    if !file.is_little_endian() {
+        return Err("Big endian binaries aren't supported".into());
+    }
+
+    let base = file.segments().map(|x| x.address()).min().unwrap_or(0);
+    let mut image = Image::new(Arch::from_object(file.architecture())?, base);
+
+    image.load_sections(&file);
+
+    println!("Loading Symbols");
+
+    for sym in file.symbols().chain(file.dynamic_symbols()) {
+        if !sym.is_definition() || sym.kind() != SymbolKind::Text || sym.address() == 0 {
+            continue;
+        }
+        let end = (sym.size() != 0).then(|| sym.address() + sym.size());
+        image.add_function(sym.address(), end);
+        if let Ok(name) = sym.name() {
+            image.symbols.insert(sym.address(), name.to_string());
+        }
+    }
+
+    eh_frame_bounds(&file, &mut image).warn_if("Unable to read .eh_frame")?;
+    if file.entry() != 0 {
+        image.add_function(file.entry(), None);
+    }
+
+    println!("Loading Strings");
+
+    image.strings = file
+        .sections()
+        .filter(|x| x.name().is_ok_and(|x| x.starts_with(".rodata")))
+        .filter_map(|x| (x.address(), x.data().ok()?).as_some())
+        .flat_map(|(addr, data)| c_strings(addr, data))
+        .collect();
+
+    println!("Loading Vtables");
+
+    image.vtables = vtables(&file, &image);
+
+    Ok(image.analyze())
+}
@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use object::{Object, ObjectSection, ObjectSegment, SectionFlags, SectionKind, SegmentFlags};
+
+use super::disasm::{self, Arch, Flow, Insn};
+use crate::db::*;
+use crate::util::demangle;
+
+pub struct Section {
+    pub addr: u64,
+    pub data: Vec<u8>,
+    pub exec: bool,
+}
+
+/// Everything a native backend pulls out of a binary before disassembling it
+pub struct Image {
+    pub arch: Arch,
+    /// Where the binary expects to be loaded
+    pub base: u64,
+    pub sections: Vec<Section>,
+    pub section_table: Vec<SectionInfo>,
+    /// Function starts, with where they end if the binary says
+    pub functions: BTreeMap<u64, Option<u64>>,
+    pub symbols: HashMap<u64, String>,
+    pub strings: HashMap<u64, String>,
+    pub vtables: HashMap<String, Vtable>,
+}
+
+// The reachable instructions of a function and the addresses blocks start at
+struct Decoded {
+    insns: BTreeMap<u64, Insn>,
+    leaders: BTreeSet<u64>,
+}
+
+/// Null terminated runs of printable text in a chunk of data, by address
+pub fn c_strings(addr: u64, data: &[u8]) -> impl Iterator<Item = (u64, String)> + '_ {
+    let mut start = 0;
+    data.iter().enumerate().filter_map(move |(i, c)| {
+        if *c != 0 {
+            return None;
+        }
+        let text = &data[start..i];
+        let text_addr = addr + start as u64;
+        start = i + 1;
+
+        (text.len() >= 4
+            && text
+                .iter()
+                .all(|x| x.is_ascii_graphic() || b" \t\n\r".contains(x)))
+        .then(|| (text_addr, String::from_utf8_lossy(text).into_owned()))
+    })
+}
+
+// Whether a section can be read, written and executed
+fn permissions<'a>(file: &impl Object<'a>, section: &impl ObjectSection<'a>) -> (bool, bool, bool) {
+    use object::{elf, macho, pe};
+
+    match section.flags() {
+        SectionFlags::Elf { sh_flags } => (
+            sh_flags & elf::SHF_ALLOC as u64 != 0,
+            sh_flags & elf::SHF_WRITE as u64 != 0,
+            sh_flags & elf::SHF_EXECINSTR as u64 != 0,
+        ),
+        SectionFlags::Coff { characteristics } => (
+            characteristics & pe::IMAGE_SCN_MEM_READ != 0,
+            characteristics & pe::IMAGE_SCN_MEM_WRITE != 0,
+            characteristics & pe::IMAGE_SCN_MEM_EXECUTE != 0,
+        ),
+        // Mach-O keeps them on the segment instead
+        _ => {
+            let segment = section.segment_name().ok().flatten();
+            let prot = file
+                .segments()
+                .find(|x| x.name().ok().flatten() == segment)
+                .and_then(|x| match x.flags() {
+                    SegmentFlags::MachO { initprot, .. } => Some(initprot),
+                    _ => None,
+                })
+                .unwrap_or(macho::VM_PROT_READ);
+            (
+                prot & macho::VM_PROT_READ != 0,
+                prot & macho::VM_PROT_WRITE != 0,
+                prot & macho::VM_PROT_EXECUTE != 0,
+            )
+        }
+    }
+}
+
+/// The class an Itanium mangled type name stands for
+pub fn type_name(mangled: &str) -> Option<String> {
+    // cpp_demangle writes vtables out as {vtable(Class)}
+    let name = demangle(&format!("_ZTV{}", mangled));
+    Some(
+        name.strip_prefix("{vtable(")?
+            .strip_suffix(")}")?
+            .to_string(),
+    )
+}
+
+impl Image {
+    pub fn new(arch: Arch, base: u64) -> Self {
+        Image {
+            arch,
+            base,
+            sections: Vec::new(),
+            section_table: Vec::new(),
+            functions: BTreeMap::new(),
+            symbols: HashMap::new(),
+            strings: HashMap::new(),
+            vtables: HashMap::new(),
+        }
+    }
+
+    /// Takes in every section that gets loaded
+    pub fn load_sections<'a>(&mut self, file: &impl Object<'a>) {
+        for section in file.sections().filter(|x| x.address() != 0) {
+            let (read, write, exec) = permissions(file, &section);
+            self.section_table.push(SectionInfo {
+                name: section.name().unwrap_or_default().to_string(),
+                addr: section.address(),
+                size: section.size(),
+                read,
+                write,
+                exec,
+            });
+
+            if let Ok(data) = section.data() {
+                self.sections.push(Section {
+                    addr: section.address(),
+                    data: data.to_vec(),
+                    exec: section.kind() == SectionKind::Text,
+                });
+            }
+        }
+    }
+
+    /// Adds a function, keeping any end already known for it
+    pub fn add_function(&mut self, start: u64, end: Option<u64>) {
+        let known = self.functions.entry(start).or_default();
+        if end.is_some() {
+            *known = end;
+        }
+    }
+
+    fn section(&self, addr: u64) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|x| addr >= x.addr && addr < x.addr + x.data.len() as u64)
+    }
+
+    /// The bytes from an address to the end of its section
+    pub fn bytes(&self, addr: u64) -> Option<&[u8]> {
+        let section = self.section(addr)?;
+        Some(&section.data[(addr - section.addr) as usize..])
+    }
+
+    pub fn is_exec(&self, addr: u64) -> bool {
+        self.section(addr).is_some_and(|x| x.exec)
+    }
+
+    pub fn read_u64(&self, addr: u64) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.bytes(addr)?.get(..8)?.try_into().ok()?,
+        ))
+    }
+
+    /// The null terminated string at an address
+    pub fn c_string(&self, addr: u64) -> Option<&str> {
+        let bytes = self.bytes(addr)?;
+        std::str::from_utf8(&bytes[..bytes.iter().position(|x| *x == 0)?]).ok()
+    }
+
+    // Where a function ends, if the binary didn't say then wherever the next one starts
+    fn function_end(&self, start: u64) -> u64 {
+        let next = self.functions.range(start + 1..).next().map(|x| *x.0);
+        let section_end = self
+            .section(start)
+            .map(|x| x.addr + x.data.len() as u64)
+            .unwrap_or(start);
+
+        self.functions[&start]
+            .or(next)
+            .unwrap_or(section_end)
+            .min(section_end)
+    }
+
+    // Whether an address is inside a function the binary gave the bounds of
+    fn inside_known(&self, addr: u64) -> bool {
+        self.functions
+            .range(..=addr)
+            .next_back()
+            .is_some_and(|(_, end)| end.is_some_and(|x| x > addr))
+    }
+
+    fn decode_function(&self, start: u64, end: u64) -> Decoded {
+        let mut decoder = disasm::decoder(self.arch);
+        let mut insns = BTreeMap::new();
+        let mut leaders = BTreeSet::from([start]);
+        let mut work = vec![start];
+
+        let in_range = |x: u64| x >= start && x < end;
+
+        while let Some(mut pc) = work.pop() {
+            while in_range(pc) && !insns.contains_key(&pc) {
+                let Some(insn) = self
+                    .bytes(pc)
+                    .and_then(|x| decoder.decode(pc, &x[..x.len().min((end - pc) as usize)]))
+                else {
+                    break;
+                };
+                let next = pc + insn.len;
+                let flow = insn.flow;
+                insns.insert(pc, insn);
+
+                match flow {
+                    Flow::Next | Flow::Call(_) => {
+                        pc = next;
+                        continue;
+                    }
+                    Flow::Jump(Some(target)) if in_range(target) => {
+                        leaders.insert(target);
+                        work.push(target);
+                    }
+                    Flow::Cond(_, target) => {
+                        if in_range(target) {
+                            leaders.insert(target);
+                            work.push(target);
+                        }
+                        leaders.insert(next);
+                        work.push(next);
+                    }
+                    _ => {}
+                }
+                break;
+            }
+        }
+
+        Decoded { insns, leaders }
+    }
+
+    /// Disassembles every function, finding the ones only reached by calls along the way
+    pub fn analyze(mut self) -> ExecDB {
+        println!("Functions: {}", self.functions.len());
+        println!("Disassembling");
+
+        let mut decoded: HashMap<u64, Decoded> = HashMap::new();
+        let mut work: Vec<u64> = self.functions.keys().copied().collect();
+        while let Some(start) = work.pop() {
+            if decoded.contains_key(&start) {
+                continue;
+            }
+
+            let func = self.decode_function(start, self.function_end(start));
+            for insn in func.insns.values() {
+                if let Flow::Call(Some(target)) = insn.flow {
+                    if !self.functions.contains_key(&target)
+                        && self.is_exec(target)
+                        && !self.inside_known(target)
+                    {
+                        self.functions.insert(target, None);
+                        work.push(target);
+                    }
+                }
+            }
+            decoded.insert(start, func);
+        }
+
+        println!("Functions Found: {}", decoded.len());
+        println!("Loading Blocks");
+
+        let mut xrefs: HashMap<u64, Vec<Address>> = HashMap::new();
+        let mut strings: HashMap<String, StringRef> = HashMap::new();
+        let mut functions = HashMap::new();
+
+        for (start, func) in decoded {
+            let mut blocks = Vec::new();
+
+            for leader in &func.leaders {
+                if !func.insns.contains_key(leader) {
+                    continue;
+                }
+
+                let mut block = Block {
+                    address: Address {
+                        addr: *leader,
+                        block_addr: *leader,
+                        function_addr: start,
+                    },
+                    calls: Vec::new(),
+                    branch: Branch::Return,
+                    strings: Vec::new(),
+                };
+
+                let mut pc = *leader;
+                while let Some(insn) = func.insns.get(&pc) {
+                    let site = Address {
+                        addr: pc,
+                        block_addr: *leader,
+                        function_addr: start,
+                    };
+
+                    if let Flow::Call(target) = insn.flow {
+                        match target.filter(|x| self.functions.contains_key(x)) {
+                            Some(x) => {
+                                block.calls.push(Dest::Known(x));
+                                xrefs.entry(x).or_default().push(site);
+                            }
+                            None => block.calls.push(Dest::Unknown),
+                        }
+                    }
+
+                    for string in insn.refs.iter().filter_map(|x| self.strings.get(x)) {
+                        block.strings.push(string.clone());
+                        strings
+                            .entry(string.clone())
+                            .or_insert_with(|| StringRef {
+                                string: string.clone(),
+                                xrefs: Vec::new(),
+                            })
+                            .xrefs
+                            .push(site);
+                    }
+
+                    let next = pc + insn.len;
+                    let fail = Dest::Known(next);
+                    let ends = !func.insns.contains_key(&next) || func.leaders.contains(&next);
+                    block.branch = match insn.flow {
+                        Flow::Return | Flow::Stop => Branch::Return,
+                        Flow::Jump(Some(x)) => Branch::Neutral(Dest::Known(x)),
+                        Flow::Jump(None) => Branch::Neutral(Dest::Unknown),
+                        Flow::Cond(cond, x) => cond.branch(Dest::Known(x), fail),
+                        Flow::Next | Flow::Call(_) if ends => Branch::Neutral(fail),
+                        Flow::Next | Flow::Call(_) => {
+                            pc = next;
+                            continue;
+                        }
+                    };
+                    break;
+                }
+
+                blocks.push(block);
+            }
+
+            functions.insert(
+                start,
+                Function {
+                    name: self.symbols.get(&start).cloned(),
+                    address: Address {
+                        addr: start,
+                        block_addr: start,
+                        function_addr: start,
+                    },
+                    blocks,
+                    xrefs: Vec::new(),
+                },
+            );
+        }
+
+        for (addr, sites) in xrefs {
+            if let Some(func) = functions.get_mut(&addr) {
+                func.xrefs = sites;
+            }
+        }
+
+        println!(
+            "Xrefs Found: {}",
+            functions.values().filter(|x| !x.xrefs.is_empty()).count()
+        );
+        println!("Strings Found: {}", strings.len());
+        println!("Done");
+
+        ExecDB {
+            header: ExecHeader::default(),
+            meta: Metadata {
+                arch: self.arch.name().to_string(),
+                bits: 64,
+                endian: Endian::Little,
+                base: self.base,
+                sections: self.section_table,
+            },
+            fns: functions,
+            vtables: self.vtables,
+            strings,
+        }
+    }
+}
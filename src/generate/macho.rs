@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use object::macho::LC_FUNCTION_STARTS;
+use object::read::macho::{
+    FatArch, LoadCommandVariant, MachOFatFile32, MachOFatFile64, MachOFile64,
+};
+use object::{
+    Endianness, FileKind, Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind,
+};
+
+use super::disasm::Arch;
+use super::image::{c_strings, type_name, Image};
+use crate::db::Vtable;
+use crate::util::*;
+
+// Picks one slice out of a fat binary
+fn slice<'a, F: FatArch>(
+    arches: &[F],
+    data: &'a [u8],
+    arch: Option<Arch>,
+) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+    let available: Vec<_> = arches
+        .iter()
+        .filter_map(|x| (Arch::from_object(x.architecture()).ok()?, x).as_some())
+        .collect();
+
+    let chosen = match (arch, available.as_slice()) {
+        (Some(arch), _) => available.iter().find(|x| x.0 == arch),
+        (None, [only]) => Some(only),
+        (None, _) => None,
+    };
+
+    match chosen {
+        Some((_, x)) => Ok(x.data(data)?),
+        None => Err(format!(
+            "Pick one of the architectures in the fat binary with --arch: {}",
+            available
+                .iter()
+                .filter_map(|x| Some(x.0.to_possible_value()?.get_name().to_string()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+// Function starts are uleb128 deltas, the first one from the start of __TEXT
+fn function_starts(file: &MachOFile64<Endianness>, data: &[u8], text: u64) -> Vec<u64> {
+    let mut starts = Vec::new();
+    let Ok(mut commands) = file.macho_load_commands() else {
+        return starts;
+    };
+
+    while let Ok(Some(command)) = commands.next() {
+        let Ok(LoadCommandVariant::LinkeditData(linkedit)) = command.variant() else {
+            continue;
+        };
+        if linkedit.cmd.get(file.endian()) != LC_FUNCTION_STARTS {
+            continue;
+        }
+
+        let offset = linkedit.dataoff.get(file.endian()) as usize;
+        let size = linkedit.datasize.get(file.endian()) as usize;
+        let Some(mut bytes) = data.get(offset..offset + size) else {
+            continue;
+        };
+
+        let mut addr = text;
+        while let Some(delta) = uleb128(&mut bytes).filter(|x| *x != 0) {
+            addr += delta;
+            starts.push(addr);
+        }
+    }
+
+    starts
+}
+
+fn uleb128(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+// Reads a pointer, which may be a chained fixup rather than a plain address.
+// None means there's no pointer, Some(None) that it's bound to an import.
+fn pointer(image: &Image, addr: u64, base: u64) -> Option<Option<u64>> {
+    let raw = image.read_u64(addr)?;
+    if raw == 0 {
+        None
+    } else if raw >> 63 != 0 {
+        Some(None)
+    } else {
+        // Rebases keep their target in the low 36 bits, sometimes relative to the image base
+        let target = raw & 0xF_FFFF_FFFF;
+        Some(Some(if target < base { target + base } else { target }))
+    }
+}
+
+// Vtables found by their layout: an offset to top of zero, a pointer to typeinfo whose
+// second field points at the mangled type name, then the functions
+fn vtables(file: &MachOFile64<Endianness>, image: &Image, base: u64) -> HashMap<String, Vtable> {
+    let mut vtables = HashMap::new();
+
+    let consts = file.sections().filter(|x| {
+        x.name().is_ok_and(|x| x == "__const")
+            && x.segment_name()
+                .is_ok_and(|x| x.is_some_and(|x| x.starts_with("__DATA")))
+    });
+
+    for section in consts {
+        let end = section.address() + section.size();
+        let mut slot = section.address().next_multiple_of(8);
+
+        while slot + 16 <= end {
+            let class = (image.read_u64(slot) == Some(0))
+                .then(|| pointer(image, slot + 8, base))
+                .flatten()
+                .flatten()
+                .and_then(|typeinfo| pointer(image, typeinfo + 8, base)?)
+                .and_then(|name| type_name(image.c_string(name)?));
+
+            let Some(class) = class else {
+                slot += 8;
+                continue;
+            };
+
+            let address = slot + 16;
+            let function_addrs: Vec<_> = (0..)
+                .map(|i| address + i * 8)
+                .take_while(|x| *x < end)
+                .map_while(|x| match pointer(image, x, base)? {
+                    None => Some(0),
+                    Some(x) => image.is_exec(x).then_some(x),
+                })
+                .collect();
+
+            slot = address + function_addrs.len() as u64 * 8;
+            if !function_addrs.is_empty() && !vtables.contains_key(&class) {
+                vtables.insert(
+                    class.clone(),
+                    Vtable {
+                        name: class,
+                        address,
+                        function_addrs,
+                    },
+                );
+            }
+        }
+    }
+
+    vtables
+}
+
+pub fn generate(
+    path: PathBuf,
+    arch: Option<Arch>,
+) -> Result<crate::db::ExecDB, Box<dyn std::error::Error>> {
+    println!("Initializing");
+
+    let data = std::fs::read(path)?;
+    let data = match FileKind::parse(&*data)? {
+        FileKind::MachOFat32 => slice(MachOFatFile32::parse(&*data)?.arches(), &data, arch)?,
+        FileKind::MachOFat64 => slice(MachOFatFile64::parse(&*data)?.arches(), &data, arch)?,
+        FileKind::MachO64 => &data,
+        _ => return Err("Not a 64 bit Mach-O file".into()),
+    };
+    let file = MachOFile64::<Endianness>::parse(data)?;
+    if !file.is_little_endian() {
+        return Err("Big endian binaries aren't supported".into());
+    }
+
+    let base = file
+        .segments()
+        .find(|x| x.name().is_ok_and(|x| x == Some("__TEXT")))
+        .map(|x| x.address())
+        .ok_or("No __TEXT segment")?;
+    let mut image = Image::new(Arch::from_object(file.architecture())?, base);
+
+    image.load_sections(&file);
+
+    println!("Loading Symbols");
+
+    for addr in function_starts(&file, data, base) {
+        image.add_function(addr, None);
+    }
+
+    for sym in file.symbols() {
+        if !sym.is_definition() || sym.kind() != SymbolKind::Text || sym.address() == 0 {
+            continue;
+        }
+        image.add_function(sym.address(), None);
+        if let Ok(name) = sym.name() {
+            // Darwin puts an extra underscore in front of every symbol
+            let name = name.strip_prefix('_').unwrap_or(name);
+            image.symbols.insert(sym.address(), name.to_string());
+        }
+    }
+
+    println!("Loading Strings");
+
+    image.strings = file
+        .sections()
+        .filter(|x| x.name().is_ok_and(|x| x == "__cstring"))
+        .filter_map(|x| (x.address(), x.data().ok()?).as_some())
+        .flat_map(|(addr, data)| c_strings(addr, data))
+        .collect();
+
+    println!("Loading Vtables");
+
+    image.vtables = vtables(&file, &image, base);
+
+    Ok(image.analyze())
+}
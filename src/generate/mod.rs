@@ -1,15 +1,31 @@
+mod debug;
+mod disasm;
+pub mod elf;
 pub mod ghidra;
+mod image;
+pub mod macho;
+pub mod pe;
 pub mod rizin;
 
 use std::path::PathBuf;
 
 use clap::{Args, ValueEnum};
 use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::util::{parse_base, sha256_file};
 
 #[derive(Clone, ValueEnum)]
 pub enum Backend {
     Ghidra,
     Rizin,
+    /// Parses and disassembles an ELF directly, without any external tools
+    Elf,
+    /// Parses and disassembles a Mach-O directly, without any external tools
+    Macho,
+    /// Parses and disassembles a PE directly, without any external tools. Only PE32+ (64 bit)
+    /// is supported
+    Pe,
 }
 
 #[derive(Args)]
@@ -20,6 +36,23 @@ pub struct Generate {
 
     #[clap(short, long)]
     output: Option<PathBuf>,
+    /// Architecture to take out of a fat binary (macho only)
+    #[clap(long, value_enum)]
+    arch: Option<disasm::Arch>,
+
+    /// How many rizin processes to split the analysis across (rizin only)
+    #[clap(short, long, default_value_t = 1)]
+    jobs: usize,
+    /// Skip output rizin gets wrong instead of failing, counting how much was (rizin only)
+    #[clap(long)]
+    skip_bad_output: bool,
+
+    /// A PDB, or a binary with DWARF, to name functions from
+    #[clap(long)]
+    debug_info: Option<PathBuf>,
+    /// Address the image the PDB describes is loaded at, if not the image base of the binary
+    #[clap(long, value_parser = parse_base)]
+    pdb_base: Option<u64>,
 }
 
 impl Generate {
@@ -34,11 +67,42 @@ impl Generate {
             .ok_or("Unable to extract filename from project path")?;
         fs::File::create(&out_file)?;
 
-        let out_data = match self.backend {
-            Backend::Rizin => rizin::generate(self.proj.display().to_string()),
-            Backend::Ghidra => ghidra::generate(self.proj),
+        let mut out_data = match self.backend {
+            Backend::Rizin => rizin::generate(
+                self.proj.display().to_string(),
+                self.jobs,
+                self.skip_bad_output,
+            ),
+            Backend::Ghidra => ghidra::generate(self.proj.clone()),
+            Backend::Elf => elf::generate(self.proj.clone()),
+            Backend::Macho => macho::generate(self.proj.clone(), self.arch),
+            Backend::Pe => pe::generate(self.proj.clone()),
         }?;
-        fs::write(&out_file, pot::to_vec(&out_data)?)?;
+
+        // The native backends are handed the binary itself
+        if let Backend::Elf | Backend::Macho | Backend::Pe = self.backend {
+            out_data.header.hash = Some(sha256_file(&self.proj)?);
+        }
+        out_data.header.backend = self
+            .backend
+            .to_possible_value()
+            .map(|x| x.get_name().to_string())
+            .unwrap_or_default();
+        out_data.header.created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        if let Some(path) = self.debug_info {
+            let base = self.pdb_base.unwrap_or(out_data.meta.base);
+            if base == 0 {
+                println!("The image base isn't known, pass --pdb-base if using a PDB");
+            }
+            let names = debug::names(&path, base)?;
+            println!(
+                "Named {} functions from debug info",
+                debug::apply(&mut out_data, names)
+            );
+        }
+
+        out_data.save(&out_file)?;
         Ok(())
     }
 }
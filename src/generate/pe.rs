@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use object::{BinaryFormat, Object, ObjectSection};
+
+use super::disasm::Arch;
+use super::image::{c_strings, Image};
+use crate::db::Vtable;
+use crate::util::*;
+
+fn read_u32(image: &Image, addr: u64) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        image.bytes(addr)?.get(..4)?.try_into().ok()?,
+    ))
+}
+
+// Function bounds from the exception directory, leaving out the chained entries
+// that only describe pieces split off of another function
+fn pdata_bounds(image: &Image, pdata: &[u8], base: u64) -> Vec<(u64, u64)> {
+    match image.arch {
+        Arch::X86_64 => pdata
+            .chunks_exact(12)
+            .filter_map(|x| {
+                let begin = u32::from_le_bytes(x[0..4].try_into().ok()?) as u64;
+                let end = u32::from_le_bytes(x[4..8].try_into().ok()?) as u64;
+                let unwind = u32::from_le_bytes(x[8..12].try_into().ok()?) as u64;
+
+                // UNW_FLAG_CHAININFO
+                let flags = image.bytes(base + unwind)?.first()? >> 3;
+                (begin != 0 && flags & 0x4 == 0).then_some((base + begin, base + end))
+            })
+            .collect(),
+        Arch::Aarch64 => pdata
+            .chunks_exact(8)
+            .filter_map(|x| {
+                let begin = u32::from_le_bytes(x[0..4].try_into().ok()?) as u64;
+                let info = u32::from_le_bytes(x[4..8].try_into().ok()?);
+
+                // Packed entries carry the length, the rest point at .xdata that does
+                let len = if info & 0x3 != 0 {
+                    (info >> 2) & 0x7FF
+                } else {
+                    read_u32(image, base + info as u64)? & 0x3FFFF
+                } as u64
+                    * 4;
+                (begin != 0).then_some((base + begin, base + begin + len))
+            })
+            .collect(),
+    }
+}
+
+/// Turns an MSVC type descriptor name like `.?AVCCNode@cocos2d@@` into `cocos2d::CCNode`.
+/// Templates are left alone since their arguments would need a full demangler.
+fn msvc_class(name: &str) -> Option<String> {
+    let name = name
+        .strip_prefix(".?AV")
+        .or(name.strip_prefix(".?AU"))?
+        .strip_suffix("@@")?;
+    if name.contains(['?', '$']) {
+        return None;
+    }
+    Some(name.split('@').rev().collect::<Vec<_>>().join("::"))
+}
+
+// Vtables found through the Complete Object Locator each one is preceded by
+fn vtables(image: &Image, rdata: &[(u64, u64)], base: u64) -> HashMap<String, Vtable> {
+    let mut vtables = HashMap::new();
+    let in_rdata = |x: u64| rdata.iter().any(|(start, end)| x >= *start && x < *end);
+
+    for (start, end) in rdata {
+        let mut slot = start.next_multiple_of(8);
+        while slot + 16 <= *end {
+            let locator = image.read_u64(slot).filter(|x| in_rdata(*x));
+
+            // Only primary vtables: 64 bit signature, no offset into the class, pointing at itself
+            let class = locator
+                .filter(|x| read_u32(image, *x) == Some(1) && read_u32(image, x + 4) == Some(0))
+                .filter(|x| read_u32(image, x + 20).map(|y| base + y as u64) == Some(*x))
+                .and_then(|x| read_u32(image, x + 12))
+                .and_then(|x| image.c_string(base + x as u64 + 16))
+                .and_then(msvc_class);
+
+            let Some(class) = class else {
+                slot += 8;
+                continue;
+            };
+
+            let address = slot + 8;
+            let function_addrs: Vec<_> = (0..)
+                .map(|i| address + i * 8)
+                .take_while(|x| x < end)
+                .map_while(|x| image.read_u64(x).filter(|x| image.is_exec(*x)))
+                .collect();
+
+            slot = address + function_addrs.len() as u64 * 8;
+            if !function_addrs.is_empty() && !vtables.contains_key(&class) {
+                vtables.insert(
+                    class.clone(),
+                    Vtable {
+                        name: class,
+                        address,
+                        function_addrs,
+                    },
+                );
+            }
+        }
+    }
+
+    vtables
+}
+
+pub fn generate(path: PathBuf) -> Result<crate::db::ExecDB, Box<dyn std::error::Error>> {
+    println!("Initializing");
+
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data)?;
+    if file.format() != BinaryFormat::Pe {
+        return Err("Not a PE file".into());
+    }
+    if !file.is_64() {
+        return Err("Only PE32+ (64 bit) files are supported, use rizin or ghidra for PE32".into());
+    }
+
+    let base = file.relative_address_base();
+    let mut image = Image::new(Arch::from_object(file.architecture())?, base);
+
+    image.load_sections(&file);
+
+    println!("Loading Functions");
+
+    let pdata = file
+        .section_by_name(".pdata")
+        .and_then(|x| x.data().ok())
+        .unwrap_or_default();
+    for (start, end) in pdata_bounds(&image, pdata, base) {
+        image.add_function(start, Some(end));
+    }
+    if file.entry() != 0 {
+        image.add_function(file.entry(), None);
+    }
+
+    println!("Loading Symbols");
+
+    for export in file.exports()? {
+        if image.is_exec(export.address()) {
+            image.add_function(export.address(), None);
+            image.symbols.insert(
+                export.address(),
+                String::from_utf8_lossy(export.name()).into_owned(),
+            );
+        }
+    }
+
+    println!("Loading Strings");
+
+    let rdata: Vec<_> = file
+        .sections()
+        .filter(|x| x.name().is_ok_and(|x| x == ".rdata"))
+        .map(|x| (x.address(), x.address() + x.size()))
+        .collect();
+    image.strings = file
+        .sections()
+        .filter(|x| x.name().is_ok_and(|x| x == ".rdata"))
+        .filter_map(|x| (x.address(), x.data().ok()?).as_some())
+        .flat_map(|(addr, data)| c_strings(addr, data))
+        .collect();
+
+    println!("Loading Vtables");
+
+    image.vtables = vtables(&image, &rdata, base);
+
+    Ok(image.analyze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msvc_class_names() {
+        assert_eq!(
+            msvc_class(".?AVCCNode@cocos2d@@").as_deref(),
+            Some("cocos2d::CCNode")
+        );
+        assert_eq!(msvc_class(".?AUPoint@@").as_deref(), Some("Point"));
+        assert_eq!(
+            msvc_class(".?AVInner@Outer@ns@@").as_deref(),
+            Some("ns::Outer::Inner")
+        );
+    }
+
+    #[test]
+    fn msvc_class_leaves_templates_alone() {
+        assert_eq!(msvc_class(".?AV?$vector@HV?$allocator@H@std@@@std@@"), None);
+        assert_eq!(msvc_class(".?AV<lambda_1>@?1??main@@YAHXZ@"), None);
+        // Not a type descriptor name
+        assert_eq!(msvc_class("CCNode"), None);
+        assert_eq!(msvc_class(".?AVCCNode"), None);
+    }
+}
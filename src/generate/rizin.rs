@@ -1,43 +1,338 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::io::Write;
 
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::disasm::Cond;
 use crate::db::*;
-use crate::pipes::PipeExt;
-use crate::util::{AsSome, Warn};
+use crate::pipes::PipePool;
+use crate::util::{sha256_file, AsHex, AsSome, Warn};
+
+/// What went wrong talking to rizin
+#[derive(Debug)]
+pub enum RizinError {
+    /// rizin couldn't run the command at all
+    Command { command: String, message: String },
+    /// The command ran, but its output wasn't shaped the way it should be
+    Output {
+        command: String,
+        addr: Option<u64>,
+        message: String,
+    },
+}
 
-use rzpipe::{RzPipe, RzPipeSpawnOptions};
-use serde_json::Value;
+impl fmt::Display for RizinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RizinError::Command { command, message } => {
+                write!(f, "rizin failed to run `{}`: {}", command, message)
+            }
+            RizinError::Output {
+                command,
+                addr: Some(addr),
+                message,
+            } => write!(
+                f,
+                "Unexpected output from `{}` at {}: {}",
+                command,
+                addr.as_hex(),
+                message
+            ),
+            RizinError::Output {
+                command,
+                addr: None,
+                message,
+            } => write!(f, "Unexpected output from `{}`: {}", command, message),
+        }
+    }
+}
 
-fn hex_to_u64(hex: &str) -> Option<u64> {
-    u64::from_str_radix(hex.strip_prefix("0x").unwrap_or("j"), 16)
-        .ok()
-        .warn_if(format!("Unable to decode hex: {}", hex))
+impl std::error::Error for RizinError {}
+
+// The parts of rizin's json output that get used
+
+#[derive(Deserialize)]
+struct RzInfo {
+    core: RzCore,
+    bin: RzBin,
 }
 
-fn get_branch_type(inst: &str, jump: u64, fail: u64) -> Branch {
-    let mut iter = inst.split(" ");
-    let opcode = iter.next().unwrap();
+#[derive(Deserialize)]
+struct RzCore {
+    file: Option<String>,
+}
 
-    if opcode == "ret" {
-        Branch::Return
-    } else {
-        //let operand = iter.next().expect(&format!("Bad instruction: {} at address {}", inst, fail));
-        let fail = Dest::Known(fail);
-        let jump = if jump == 0 {
-            Dest::Unknown
-        } else {
-            Dest::Known(jump)
-        };
+#[derive(Deserialize)]
+struct RzBin {
+    arch: Option<String>,
+    #[serde(default)]
+    bits: u64,
+    endian: Option<String>,
+    #[serde(default)]
+    baddr: u64,
+}
+
+#[derive(Deserialize)]
+struct RzSection {
+    name: String,
+    vaddr: u64,
+    vsize: Option<u64>,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    perm: String,
+}
+
+/// Lists some rizin versions give bare and others wrap in an object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RzList<T> {
+    Bare(Vec<T>),
+    Sections { sections: Vec<T> },
+    Strings { strings: Vec<T> },
+}
+
+impl<T> RzList<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            RzList::Bare(x) | RzList::Sections { sections: x } | RzList::Strings { strings: x } => {
+                x
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RzFunction {
+    #[serde(alias = "addr")]
+    offset: u64,
+}
+
+#[derive(Deserialize)]
+struct RzSymbol {
+    name: String,
+    vaddr: u64,
+}
+
+#[derive(Deserialize)]
+struct RzBlock {
+    addr: u64,
+    size: u64,
+    jump: Option<u64>,
+    fail: Option<u64>,
+    /// The jump table the block ends in, if rizin found one
+    switch_op: Option<RzSwitch>,
+}
+
+#[derive(Deserialize)]
+struct RzSwitch {
+    cases: Vec<RzCase>,
+}
+
+#[derive(Deserialize)]
+struct RzCase {
+    jump: u64,
+    #[serde(default)]
+    value: u64,
+}
+
+#[derive(Deserialize)]
+struct RzDisasm {
+    ops: Vec<RzOp>,
+}
+
+#[derive(Deserialize)]
+struct RzOp {
+    #[serde(alias = "addr")]
+    offset: u64,
+    #[serde(default)]
+    size: u64,
+    #[serde(rename = "type", default)]
+    kind: String,
+    jump: Option<u64>,
+    /// The condition a conditional jump is taken on, like `eq` or `hs`
+    cond: Option<String>,
+    #[serde(default, alias = "opcode")]
+    disasm: String,
+}
+
+impl RzOp {
+    fn mnemonic(&self) -> &str {
+        let mnemonic = self.disasm.split_whitespace().next().unwrap_or_default();
+        // Thumb's width qualifiers
+        mnemonic
+            .strip_suffix(".w")
+            .or(mnemonic.strip_suffix(".n"))
+            .unwrap_or(mnemonic)
+    }
+
+    /// When a conditional jump is taken, from rizin's condition or failing that the mnemonic
+    fn condition(&self) -> Option<Cond> {
+        if let Some(cond) = self.cond.as_deref().and_then(Cond::from_name) {
+            return Some(cond);
+        }
+
+        match self.mnemonic() {
+            // Taken once the register or counter is zero
+            "cbz" | "tbz" | "jcxz" | "jecxz" | "jrcxz" => Some(Cond::Equal),
+            "cbnz" | "tbnz" | "loop" | "loope" | "loopz" | "loopne" | "loopnz" => {
+                Some(Cond::NotEqual)
+            }
+            x => x
+                .strip_prefix("b.")
+                .or(x.strip_prefix('b'))
+                .or(x.strip_prefix('j'))
+                .and_then(Cond::from_name),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RzXref {
+    from: u64,
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct RzString {
+    vaddr: u64,
+    string: String,
+}
+
+#[derive(Deserialize)]
+struct RzVtable {
+    offset: u64,
+    methods: Vec<RzMethod>,
+}
+
+#[derive(Deserialize)]
+struct RzMethod {
+    offset: u64,
+}
+
+#[derive(Deserialize)]
+struct RzRtti {
+    name: Option<String>,
+    type_desc: Option<RzTypeDesc>,
+}
+
+#[derive(Deserialize)]
+struct RzTypeDesc {
+    name: String,
+}
+
+fn run(pipe: &PipePool, command: &str) -> Result<String, RizinError> {
+    pipe.cmd(command).map_err(|message| RizinError::Command {
+        command: command.to_string(),
+        message,
+    })
+}
+
+fn query<T: DeserializeOwned>(pipe: &PipePool, command: &str) -> Result<T, RizinError> {
+    serde_json::from_str(&run(pipe, command)?).map_err(|e| RizinError::Output {
+        command: command.to_string(),
+        addr: None,
+        message: e.to_string(),
+    })
+}
+
+/// Whether output rizin gets wrong is skipped instead of failing the generate, and how often
+/// it was
+struct BadOutput {
+    skip: bool,
+    rows: usize,
+}
 
-        match opcode {
-            "ret" => Branch::Return,
-            "ble" | "blt" | "bls" | "jb" | "jl" | "jle" | "jbe" => Branch::Inequality(fail, jump),
-            "bge" | "bgt" | "bhi" | "ja" | "jg" | "jge" | "jae" => Branch::Inequality(jump, fail),
-            "beq" | "bpl" | "je" | "jz" | "jp" => Branch::Equality(jump, fail),
-            "bne" | "bmi" | "jne" | "jnz" | "jnp" => Branch::Equality(fail, jump),
-            "b" | "br" | "bx" | "bxr" | "jmp" => Branch::Neutral(jump),
-            _ => Branch::Neutral(fail),
+impl BadOutput {
+    /// Gives the error back unless skipping, in which case it's reported and counted
+    fn handle(&mut self, error: RizinError) -> Result<(), RizinError> {
+        if !self.skip {
+            return Err(error);
         }
+        println!("Skipping: {}", error);
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+/// Runs a json command at every address, giving back one result for each. Output that doesn't
+/// parse is an error, unless `bad` skips it, in which case its result is None
+fn query_bulk<T: DeserializeOwned>(
+    pipe: &PipePool,
+    command: &str,
+    addrs: &[u64],
+    bad: &mut BadOutput,
+) -> Result<Vec<Option<T>>, RizinError> {
+    let out = pipe
+        .cmd_bulk(command, addrs)
+        .map_err(|message| RizinError::Command {
+            command: command.to_string(),
+            message,
+        })?;
+
+    // Out of step output can't be matched to addresses at all, so it's never skipped
+    let lines: Vec<_> = out.lines().collect();
+    if lines.len() != addrs.len() {
+        return Err(RizinError::Output {
+            command: command.to_string(),
+            addr: None,
+            message: format!("{} results for {} addresses", lines.len(), addrs.len()),
+        });
+    }
+
+    let mut results = Vec::new();
+    for (line, addr) in lines.into_iter().zip(addrs) {
+        match serde_json::from_str(line) {
+            Ok(x) => results.push(Some(x)),
+            Err(e) => {
+                bad.handle(RizinError::Output {
+                    command: command.to_string(),
+                    addr: Some(*addr),
+                    message: e.to_string(),
+                })?;
+                results.push(None);
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// How a block ending in `op` is left
+fn get_branch_type(op: &RzOp, jump: Option<u64>, fail: u64) -> Branch {
+    let fail = Dest::Known(fail);
+    let jump = jump.or(op.jump).map(Dest::Known).unwrap_or(Dest::Unknown);
+
+    match op.kind.as_str() {
+        "ret" | "trap" => Branch::Return,
+        // Including ucjmp and rcjmp, whatever the jump goes through. A conditional return is
+        // a jump out of the function that may fall through instead
+        x if x.ends_with("cjmp") || x == "cret" => {
+            let jump = if x == "cret" { Dest::Unknown } else { jump };
+            match op.condition().warn_if(format!(
+                "Unknown condition at {}: {}",
+                op.offset.as_hex(),
+                op.disasm
+            )) {
+                Some(cond) => cond.branch(jump, fail),
+                // Which way round the targets go would only be made up, so only the fall
+                // through is kept
+                None => Branch::Neutral(fail),
+            }
+        }
+        x if x.ends_with("jmp") => Branch::Neutral(jump),
+        _ => Branch::Neutral(fail),
+    }
+}
+
+// Names an architecture the way the native backends do
+fn arch_name(arch: &str, bits: u64) -> String {
+    match (arch, bits) {
+        ("x86", 64) => String::from("x86_64"),
+        ("arm", 64) => String::from("aarch64"),
+        (arch, _) => arch.to_string(),
     }
 }
 
@@ -66,70 +361,101 @@ fn nearest_block(val: u64, possible: &Vec<u64>) -> Option<u64> {
     Some(possible[high])
 }
 
-pub fn generate(rizin_proj: impl ToString) -> Result<ExecDB, Box<dyn std::error::Error>> {
+pub fn generate(
+    rizin_proj: impl ToString,
+    jobs: usize,
+    skip_bad: bool,
+) -> Result<ExecDB, Box<dyn std::error::Error>> {
     println!("Initializing");
 
-    let mut pipe = RzPipe::spawn(
-        "-M",
-        Some(RzPipeSpawnOptions {
-            exepath: String::from("rizin"),
-            args: vec!["-p", rizin_proj.to_string().leak()],
-        }),
-    )?;
-
-    let label_map: HashMap<String, u64> = pipe
-        .cmd("aflq")?
-        .lines()
-        .map(|x| x.split_whitespace())
-        .filter_map(|mut x| (hex_to_u64(x.next()?)?, x.collect()).as_some())
-        .map(|(x, y)| (y, x))
+    let pipe = PipePool::spawn(&rizin_proj.to_string(), jobs)?;
+
+    let info: RzInfo = query(&pipe, "ij")?;
+    let header = ExecHeader {
+        hash: info.core.file.and_then(|x| sha256_file(x.as_ref()).ok()),
+        ..Default::default()
+    };
+
+    let sections: RzList<RzSection> = query(&pipe, "iSj")?;
+    let meta = Metadata {
+        arch: info
+            .bin
+            .arch
+            .map(|x| arch_name(&x, info.bin.bits))
+            .unwrap_or_default(),
+        bits: info.bin.bits as u32,
+        endian: match info.bin.endian {
+            Some(x) if x.to_lowercase().starts_with('b') => Endian::Big,
+            _ => Endian::Little,
+        },
+        base: info.bin.baddr,
+        sections: sections
+            .into_vec()
+            .into_iter()
+            .map(|x| SectionInfo {
+                name: x.name,
+                addr: x.vaddr,
+                size: x.vsize.unwrap_or(x.size),
+                read: x.perm.contains('r'),
+                write: x.perm.contains('w'),
+                exec: x.perm.contains('x'),
+            })
+            .collect(),
+    };
+
+    let mut function_addrs: Vec<u64> = query::<Vec<RzFunction>>(&pipe, "aflj")?
+        .into_iter()
+        .map(|x| x.offset)
         .collect();
-    let function_addrs: Vec<u64> = label_map.values().map(|x| *x).collect();
-
-    let block_pool: BTreeMap<u64, u64> = pipe
-        .cmd_bulk("afbj @@. {}", &function_addrs)?
-        .lines()
+    function_addrs.sort();
+    function_addrs.dedup();
+    let function_set: HashSet<u64> = function_addrs.iter().copied().collect();
+
+    // Functions rizin gave bad output for, which are left out
+    let mut skipped: HashSet<u64> = HashSet::new();
+    let mut bad = BadOutput {
+        skip: skip_bad,
+        rows: 0,
+    };
+
+    let fn_blocks: Vec<Vec<RzBlock>> = query_bulk(&pipe, "afbj @@. {}", &function_addrs, &mut bad)?
+        .into_iter()
         .zip(&function_addrs)
-        .filter_map(|(x, y)| (serde_json::from_str::<Vec<Value>>(x).ok()?, y).as_some())
-        .map(|(x, y)| {
-            x.into_iter()
-                .filter_map(|x| x.get("addr").and_then(|x| x.as_u64()))
-                .map(|x| (x, *y))
+        .map(|(x, addr)| {
+            x.unwrap_or_else(|| {
+                skipped.insert(*addr);
+                Vec::new()
+            })
         })
-        .flatten()
         .collect();
-    let block_keys: Vec<_> = block_pool.keys().map(|x| *x).collect();
+    let block_pool: BTreeMap<u64, u64> = fn_blocks
+        .iter()
+        .zip(&function_addrs)
+        .flat_map(|(x, y)| x.iter().map(move |x| (x.addr, *y)))
+        .collect();
+    let block_keys: Vec<_> = block_pool.keys().copied().collect();
 
     println!("Blocks: {}", block_keys.len());
 
     println!("Loading Symbols");
 
-    let symbols: HashMap<u64, String> = pipe
-        .cmd("isq~Z")?
-        .lines()
-        .map(|x| x.split_whitespace())
-        .filter_map(|mut x| (hex_to_u64(x.next()?)?, x.skip(1).next()?.to_string()).as_some())
+    // Only mangled names are worth porting
+    let symbols: HashMap<u64, String> = query::<Vec<RzSymbol>>(&pipe, "isj")?
+        .into_iter()
+        .filter(|x| x.name.contains("_Z"))
+        .map(|x| (x.vaddr, x.name))
         .collect();
 
     println!("Loading Vtables");
 
-    let vtables_raw: Vec<(u64, Vec<u64>)> = pipe
-        .cmdj("avj")?
-        .as_array()
-        .unwrap()
+    let vtables_raw: Vec<(u64, Vec<u64>)> = query::<Vec<RzVtable>>(&pipe, "avj")?
         .into_iter()
         .map(|x| {
             (
-                x.get("offset").unwrap().as_u64().unwrap(),
-                x.get("methods")
-                    .unwrap()
-                    .as_array()
-                    .unwrap()
+                x.offset,
+                x.methods
                     .into_iter()
-                    .map(|x| {
-                        nearest_block(x.get("offset").unwrap().as_u64().unwrap(), &block_keys)
-                            .unwrap()
-                    })
+                    .map(|x| nearest_block(x.offset, &block_keys).unwrap_or(x.offset))
                     .collect(),
             )
         })
@@ -137,218 +463,199 @@ pub fn generate(rizin_proj: impl ToString) -> Result<ExecDB, Box<dyn std::error:
 
     let vtable_addrs: Vec<u64> = vtables_raw.iter().map(|x| x.0).collect();
 
-    let vtables: HashMap<String, Vtable> = pipe
-        .cmd_bulk("avrj @@= `cat {}`", &vtable_addrs)?
-        .lines()
-        .zip(vtables_raw)
-        .filter_map(|(x, y)| {
-            (
-                serde_json::from_str::<Vec<Value>>(x)
-                    .ok()?
-                    .into_iter()
-                    .next()?,
-                y,
-            )
-                .as_some()
-        })
-        .map(|(x, y)| {
-            (
-                x.get("type_desc")
-                    .unwrap_or(&x)
-                    .get("name")
-                    .and_then(|x| x.as_str())
-                    .unwrap_or("")
-                    .to_string(),
-                y,
-            )
-        })
-        .map(|(x, y)| Vtable {
-            name: pipe
-                .cmd(&format!("avrD \"{}\"", x))
-                .unwrap()
-                .trim()
-                .to_string(),
-            address: y.0,
-            function_addrs: y.1,
-        })
-        .map(|x| (x.name.to_string(), x))
-        .collect();
+    let mut vtables = HashMap::new();
+    let rttis: Vec<Option<Vec<RzRtti>>> =
+        query_bulk(&pipe, "avrj @@= `cat {}`", &vtable_addrs, &mut bad)?;
+    for (rtti, (address, function_addrs)) in rttis.into_iter().zip(vtables_raw) {
+        // Vtables without RTTI have nothing to name them by
+        let Some(rtti) = rtti.into_iter().flatten().next() else {
+            continue;
+        };
+        let raw_name = rtti
+            .type_desc
+            .map(|x| x.name)
+            .or(rtti.name)
+            .unwrap_or_default();
+        let name = run(&pipe, &format!("avrD \"{}\"", raw_name))?
+            .trim()
+            .to_string();
+
+        vtables.insert(
+            name.clone(),
+            Vtable {
+                name,
+                address,
+                function_addrs,
+            },
+        );
+    }
 
     println!("Loading Xrefs");
 
-    let xrefs_raw: Vec<(u64, Vec<u64>)> = pipe
-        .cmd_bulk("axtj @@. {}", &function_addrs)?
-        .lines()
-        .filter_map(|x| serde_json::from_str::<Vec<Value>>(x).ok())
-        .filter(|x| !x.is_empty())
-        .filter_map(|x| {
-            (
-                x.first().unwrap().get("to").and_then(|x| x.as_u64())?,
-                x.into_iter()
-                    .filter_map(|x| {
-                        (x.get("type").and_then(|x| x.as_str()).unwrap_or("") == "CALL")
-                            .then(|| x.get("from").and_then(|x| x.as_u64()))?
-                    })
-                    .collect(),
-            )
-                .as_some()
-        })
-        .collect();
-    let xrefs: HashMap<u64, Vec<Address>> = xrefs_raw
-        .into_iter()
-        .map(|(x, y)| {
-            (
-                x,
-                y.into_iter()
-                    .filter_map(|val| (val, nearest_block(val, &block_keys)?).as_some())
-                    .map(|(adr, blk)| Address {
-                        addr: adr,
-                        block_addr: blk,
-                        function_addr: *block_pool.get(&blk).unwrap_or(&0),
-                    })
-                    .collect::<Vec<_>>(),
-            )
-        })
-        .collect();
+    let xrefs: HashMap<u64, Vec<Address>> =
+        query_bulk::<Vec<RzXref>>(&pipe, "axtj @@. {}", &function_addrs, &mut bad)?
+            .into_iter()
+            .zip(&function_addrs)
+            .filter_map(|(x, y)| (x.filter(|x| !x.is_empty())?, y).as_some())
+            .map(|(x, y)| {
+                (
+                    *y,
+                    x.into_iter()
+                        .filter(|x| x.kind.eq_ignore_ascii_case("CALL"))
+                        .filter_map(|x| (x.from, nearest_block(x.from, &block_keys)?).as_some())
+                        .map(|(adr, blk)| Address {
+                            addr: adr,
+                            block_addr: blk,
+                            function_addr: *block_pool.get(&blk).unwrap_or(&0),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
 
     println!("Xrefs Found: {}", xrefs.len());
 
-    println!("Loading Blocks");
+    print!("Disassembling");
 
-    // Vec<(block_addr, size, jump_addr)
-    let blocks_raw: Vec<(u64, u64, u64)> = pipe
-        .cmd_bulk("abi @@. {}", &block_keys)?
-        .lines()
-        .map(|x| x.split_whitespace().collect::<Vec<_>>())
-        .filter_map(|x| {
-            (
-                hex_to_u64(x[0])?,
-                x[3].parse().ok()?,
-                x.get(5).and_then(|x| hex_to_u64(x)).unwrap_or(0),
-            )
-                .as_some()
-        })
-        .collect();
-    let block_ends: Vec<_> = blocks_raw.iter().map(|x| x.0 + x.1).collect();
+    // Each rizin gets 100 functions a batch
+    let len = function_addrs.len();
+    let step = 100 * pipe.len();
+    let mut ops: Vec<RzOp> = Vec::new();
+    for x in (0..len).step_by(step) {
+        print!("\rDisassembling {} / {}", x / step, len / step);
+        std::io::stdout().flush().unwrap();
+
+        let batch = &function_addrs[x..std::cmp::min(x + step, len)];
+        let disasm: Vec<Option<RzDisasm>> = query_bulk(&pipe, "pdfj @@. {}", batch, &mut bad)?;
+        for (disasm, addr) in disasm.into_iter().zip(batch) {
+            match disasm {
+                Some(x) => ops.extend(x.ops),
+                None => {
+                    skipped.insert(*addr);
+                }
+            }
+        }
+    }
 
+    println!("\rDisassembling {} / {}", len / step, len / step);
     println!("Loading Branches");
 
-    // No calls yet!!
-    let mut blocks: HashMap<u64, Block> = pipe
-        .cmd_bulk("pi -1 @@. {}", &block_ends)?
-        .lines()
-        .zip(blocks_raw)
-        .map(|(instr, (addr, size, jump))| {
-            (
-                addr,
-                Block {
-                    address: Address {
-                        addr: addr,
-                        block_addr: addr,
-                        function_addr: *block_pool.get(&addr).unwrap(),
-                    },
-                    branch: get_branch_type(&instr, jump, addr + size),
-                    calls: Vec::new(),
-                    strings: Vec::new(),
-                },
-            )
-        })
+    // pdfj leaves the condition out on some versions, which aoj always has
+    let uncond: Vec<_> = ops
+        .iter()
+        .filter(|x| x.kind.ends_with("cjmp") && x.cond.is_none())
+        .map(|x| x.offset)
         .collect();
+    let conds: HashMap<u64, String> =
+        query_bulk::<Vec<RzOp>>(&pipe, "aoj @@. {}", &uncond, &mut bad)?
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|x| (x.offset, x.cond?).as_some())
+            .collect();
+    for op in &mut ops {
+        if op.cond.is_none() {
+            op.cond = conds.get(&op.offset).cloned();
+        }
+    }
 
-    print!("Finding Calls");
+    // Blocks end with whichever instruction finishes at their end
+    let ops_by_end: HashMap<u64, &RzOp> = ops.iter().map(|x| (x.offset + x.size, x)).collect();
 
-    //TODO: replace with function_addrs.len()
-    let len = function_addrs.len();
-    let call_pool: Vec<(u64, Dest)> = (0..len)
-        .step_by(100)
-        .filter_map(|x| {
-            print!("\rFinding Calls {} / {}", x / 100, len / 100);
-            std::io::stdout().flush().unwrap();
+    let mut blocks: HashMap<u64, Block> = HashMap::new();
+    for block in fn_blocks.iter().flatten() {
+        let function_addr = block_pool[&block.addr];
+        if skipped.contains(&function_addr) {
+            continue;
+        }
 
-            let upper = std::cmp::min(x + 100, len);
-            let batch = &function_addrs[x..upper];
+        let end = block.addr + block.size;
+        let Some(last) = ops_by_end.get(&end) else {
+            bad.handle(RizinError::Output {
+                command: String::from("pdfj"),
+                addr: Some(block.addr),
+                message: String::from("no instruction ends the block"),
+            })?;
+            skipped.insert(function_addr);
+            continue;
+        };
 
-            Some(
-                pipe.cmd_bulk("pDq `afi~size[1]` @@= `cat {}`", &batch)
-                    .warn_if("Call find failed!")
-                    .ok()?,
-            )
-        })
-        .map(|x| {
-            x.lines()
-                .filter(|x| {
-                    x.contains(" call ")
-                        || x.contains(" bl ")
-                        || x.contains(" blr ")
-                        || x.contains(" blx ")
-                })
-                .map(|x| x.split_whitespace().map(|x| x.to_string()))
-                .filter_map(|mut x| {
-                    (hex_to_u64(&x.next()?)?, x.skip(1).collect::<String>()).as_some()
-                })
-                .filter_map(|(x, y)| {
-                    (
-                        nearest_block(x, &block_keys)?,
-                        label_map
-                            .get(&y)
-                            .map(|x| Dest::Known(*x))
-                            .unwrap_or(Dest::Unknown),
-                    )
-                        .as_some()
-                })
-                .collect::<Vec<_>>()
-        })
-        .flatten()
-        .collect();
+        blocks.insert(
+            block.addr,
+            Block {
+                address: Address {
+                    addr: block.addr,
+                    block_addr: block.addr,
+                    function_addr,
+                },
+                branch: match &block.switch_op {
+                    Some(switch) => {
+                        let mut cases: Vec<_> = switch.cases.iter().collect();
+                        cases.sort_by_key(|x| x.value);
+                        Branch::Switch(
+                            cases.into_iter().map(|x| Dest::Known(x.jump)).collect(),
+                            block.fail.map(Dest::Known).unwrap_or(Dest::Unknown),
+                        )
+                    }
+                    None => get_branch_type(last, block.jump, end),
+                },
+                calls: Vec::new(),
+                strings: Vec::new(),
+            },
+        );
+    }
 
-    println!("\rFinding Calls {} / {}", len / 100, len / 100);
     println!("Loading Calls");
 
-    call_pool.into_iter().for_each(|(x, y)| {
-        blocks
-            .get_mut(&x)
-            .warn_if(format!("Block not found: {}", x))
-            .map(|x| x.calls.push(y));
-    });
+    ops.iter()
+        .filter(|x| x.kind.ends_with("call"))
+        .filter_map(|x| {
+            let dest = match x.jump {
+                Some(jump) if x.kind == "call" && function_set.contains(&jump) => Dest::Known(jump),
+                _ => Dest::Unknown,
+            };
+            (nearest_block(x.offset, &block_keys)?, dest).as_some()
+        })
+        .for_each(|(x, y)| {
+            blocks
+                .get_mut(&x)
+                .warn_if(format!("Block not found: {}", x))
+                .map(|x| x.calls.push(y));
+        });
+
     println!("Loading Strings");
 
-    let strings_raw: Vec<(u64, String)> = pipe
-        .cmd("izq")?
-        .lines()
-        .map(|x| x.split_whitespace())
-        .filter_map(|mut x| (hex_to_u64(x.next()?)?, x.skip(2).collect::<String>()).as_some())
-        .collect();
-    let string_addrs = strings_raw.iter().map(|x| x.0).collect::<Vec<_>>();
-
-    let strings: HashMap<String, StringRef> = pipe
-        .cmd_bulk("axtj @@. {}", &string_addrs)?
-        .lines()
-        .zip(strings_raw)
-        .filter_map(|(x, y)| (serde_json::from_str::<Vec<Value>>(x).ok()?, y).as_some())
-        .map(|(x, y)| StringRef {
-            string: y.1,
-            xrefs: x
-                .into_iter()
-                .filter_map(|x| x.get("from").and_then(|x| x.as_u64()))
-                .filter_map(|x| (x, nearest_block(x, &block_keys)?).as_some())
-                .filter_map(|(x, y)| {
-                    Some(Address {
-                        addr: x,
-                        block_addr: y,
-                        function_addr: block_pool.get(&y).cloned()?,
+    let strings_raw: Vec<RzString> = query::<RzList<RzString>>(&pipe, "izj")?.into_vec();
+    let string_addrs = strings_raw.iter().map(|x| x.vaddr).collect::<Vec<_>>();
+
+    let strings: HashMap<String, StringRef> =
+        query_bulk::<Vec<RzXref>>(&pipe, "axtj @@. {}", &string_addrs, &mut bad)?
+            .into_iter()
+            .zip(strings_raw)
+            .filter_map(|(x, y)| (x?, y).as_some())
+            .map(|(x, y)| StringRef {
+                string: y.string,
+                xrefs: x
+                    .into_iter()
+                    .filter_map(|x| (x.from, nearest_block(x.from, &block_keys)?).as_some())
+                    .filter_map(|(x, y)| {
+                        Some(Address {
+                            addr: x,
+                            block_addr: y,
+                            function_addr: block_pool.get(&y).cloned()?,
+                        })
                     })
-                })
-                .collect(),
-        })
-        .filter(|x| x.xrefs.len() > 0)
-        .fold(HashMap::<String, StringRef>::new(), |mut h, r| {
-            if let Some(x) = h.get_mut(&r.string) {
-                x.xrefs.extend(r.xrefs);
-            } else {
-                h.insert(r.string.clone(), r);
-            }
-            h
-        });
+                    .collect(),
+            })
+            .filter(|x| x.xrefs.len() > 0)
+            .fold(HashMap::<String, StringRef>::new(), |mut h, r| {
+                if let Some(x) = h.get_mut(&r.string) {
+                    x.xrefs.extend(r.xrefs);
+                } else {
+                    h.insert(r.string.clone(), r);
+                }
+                h
+            });
 
     strings.values().for_each(|x| {
         x.xrefs.iter().for_each(|y| {
@@ -360,8 +667,14 @@ pub fn generate(rizin_proj: impl ToString) -> Result<ExecDB, Box<dyn std::error:
 
     println!("Loading Functions");
 
+    if bad.rows > 0 {
+        println!("Skipped Bad Output: {}", bad.rows);
+        println!("Skipped Functions: {}", skipped.len());
+    }
+
     let mut functions: HashMap<u64, Function> = function_addrs
         .into_iter()
+        .filter(|x| !skipped.contains(x))
         .map(|x| {
             (
                 x,
@@ -380,18 +693,97 @@ pub fn generate(rizin_proj: impl ToString) -> Result<ExecDB, Box<dyn std::error:
         .collect();
 
     blocks.drain().for_each(|(_, x)| {
-        functions
-            .get_mut(&x.address.function_addr)
-            .unwrap()
-            .blocks
-            .push(x);
+        // Blocks of a function skipped after they were loaded go with it
+        if let Some(func) = functions.get_mut(&x.address.function_addr) {
+            func.blocks.push(x);
+        }
     });
 
     println!("Done");
 
     Ok(ExecDB {
+        header,
+        meta,
         fns: functions,
         vtables: vtables,
         strings: strings,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(kind: &str, disasm: &str, cond: Option<&str>) -> RzOp {
+        RzOp {
+            offset: 0x100,
+            size: 4,
+            kind: kind.to_string(),
+            jump: Some(0x200),
+            cond: cond.map(str::to_string),
+            disasm: disasm.to_string(),
+        }
+    }
+
+    #[test]
+    fn condition_from_mnemonic() {
+        assert_eq!(
+            op("cjmp", "beq.w 0x200", None).condition(),
+            Some(Cond::Equal)
+        );
+        assert_eq!(
+            op("cjmp", "bne.n 0x200", None).condition(),
+            Some(Cond::NotEqual)
+        );
+        assert_eq!(
+            op("cjmp", "b.hs 0x200", None).condition(),
+            Some(Cond::Greater)
+        );
+        assert_eq!(op("cjmp", "jl 0x200", None).condition(), Some(Cond::Less));
+        assert_eq!(
+            op("cjmp", "cbnz x0, 0x200", None).condition(),
+            Some(Cond::NotEqual)
+        );
+        assert_eq!(
+            op("cjmp", "jrcxz 0x200", None).condition(),
+            Some(Cond::Equal)
+        );
+        // rizin's own condition wins
+        assert_eq!(
+            op("cjmp", "beq 0x200", Some("lt")).condition(),
+            Some(Cond::Less)
+        );
+    }
+
+    #[test]
+    fn unknown_conditions_are_left_unknown() {
+        assert_eq!(
+            get_branch_type(&op("cjmp", "bfoo 0x200", None), None, 0x104),
+            Branch::Neutral(Dest::Known(0x104))
+        );
+        assert_eq!(
+            get_branch_type(&op("cjmp", "bne.w 0x200", None), None, 0x104),
+            Branch::Equality(Dest::Known(0x104), Dest::Known(0x200))
+        );
+        assert_eq!(
+            get_branch_type(&op("jmp", "b 0x200", None), None, 0x104),
+            Branch::Neutral(Dest::Known(0x200))
+        );
+        assert_eq!(
+            get_branch_type(&op("ret", "ret", None), None, 0x104),
+            Branch::Return
+        );
+    }
+
+    #[test]
+    fn conditional_returns_fall_through() {
+        assert_eq!(
+            get_branch_type(&op("cret", "bxeq lr", Some("eq")), None, 0x104),
+            Branch::Equality(Dest::Unknown, Dest::Known(0x104))
+        );
+        assert_eq!(
+            get_branch_type(&op("cret", "bxfoo lr", None), None, 0x104),
+            Branch::Neutral(Dest::Known(0x104))
+        );
+    }
+}
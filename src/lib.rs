@@ -0,0 +1,12 @@
+pub mod analysis;
+pub mod cfg;
+pub mod compare;
+pub mod db;
+pub mod export;
+pub mod generate;
+mod pipes;
+mod prompt;
+pub mod review;
+pub mod strategy;
+pub mod tui;
+pub mod util;
@@ -1,15 +1,15 @@
 use colored::Colorize;
 use std::path::PathBuf;
 
-mod analysis;
-mod db;
-mod generate;
-mod pipes;
-mod util;
+use symbo::db::*;
+use symbo::export;
+use symbo::generate;
+use symbo::review::{self, ConflictPolicy, ReviewQueue};
+use symbo::strategy::Registry;
+use symbo::tui;
+use symbo::util::{demangle, parse_base, AsHex};
 
-use crate::db::*;
-
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "Symbo")]
@@ -21,12 +21,34 @@ struct Cli {
 #[derive(Subcommand)]
 enum Command {
     Generate(generate::Generate),
+    /// Write the symbols in a symdb out for other tools
+    Export(export::Export),
+    /// Port symbols from one exdb to another
     Run {
         from: PathBuf,
         to: PathBuf,
         #[clap(short, long)]
         output: Option<PathBuf>,
+        /// Strategies to run each round, in order (see `strategies` for the full list)
+        #[clap(
+            short,
+            long,
+            value_delimiter = ',',
+            default_value = "string-xref,call-xref,call-block,block-traverse"
+        )]
+        strategy: Vec<String>,
+        /// Give up after this many rounds even if new symbols are still being found
+        #[clap(long, default_value_t = 16)]
+        max_rounds: usize,
+        /// Settle conflicts with this policy instead of asking, recording them in a review file
+        #[clap(long, value_enum, value_name = "POLICY")]
+        non_interactive: Option<ConflictPolicy>,
+        /// Pair the binaries even if their architectures don't match
+        #[clap(long)]
+        force: bool,
     },
+    /// List the strategies that can be passed to `run`
+    Strategies,
     Print {
         exec: PathBuf,
         addr: u64,
@@ -35,36 +57,105 @@ enum Command {
     Strip {
         file: PathBuf,
     },
+    /// Go through the conflicts queued up in a symdb's review file
+    Review {
+        file: PathBuf,
+        /// Input exdb, to show the functions involved side by side
+        #[clap(long, requires = "to")]
+        from: Option<PathBuf>,
+        /// Output exdb, to show the functions involved side by side
+        #[clap(long, requires = "from")]
+        to: Option<PathBuf>,
+    },
+    /// Browse and edit a symdb full screen
+    Tui {
+        symdb: PathBuf,
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Shift every address in an exdb or symdb to a new image base
+    Rebase {
+        file: PathBuf,
+        #[clap(value_parser = parse_base)]
+        base: u64,
+        /// Base the symdb was made against, for symdbs that don't record it
+        #[clap(long, value_parser = parse_base)]
+        from: Option<u64>,
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// List the symbols in a symdb along with why they were bound
+    List {
+        file: PathBuf,
+        #[clap(short, long, value_enum, default_value_t = SortBy::Confidence)]
+        sort: SortBy,
+    },
+}
+
+#[derive(Clone, ValueEnum)]
+enum SortBy {
+    /// Most confident first
+    Confidence,
+    Name,
+    Address,
 }
 
 fn main() {
     let args = Cli::parse();
+    let registry = Registry::default();
 
     match args.command {
         Command::Generate(gen) => {
             gen.generate().unwrap();
         }
-        Command::Run { from, to, output } => {
+        Command::Export(export) => {
+            export.export().unwrap();
+        }
+        Command::Run {
+            from,
+            to,
+            output,
+            strategy,
+            max_rounds,
+            non_interactive,
+            force,
+        } => {
+            let strats = registry.select(&strategy).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+
             let pair = ExecPair {
-                input: pot::from_slice(&std::fs::read(from).unwrap()).expect("Invalid exdb file"),
-                output: pot::from_slice(&std::fs::read(to).unwrap()).expect("Invalid exdb file"),
+                input: ExecDB::load(&from).expect("Invalid exdb file"),
+                output: ExecDB::load(&to).expect("Invalid exdb file"),
             };
+            if let Err(e) = pair.check_compatible() {
+                if !force {
+                    eprintln!("{} (pass --force to run anyway)", e);
+                    std::process::exit(1);
+                }
+                println!("{}", e.yellow());
+            }
 
             let file_path = output.unwrap_or(PathBuf::from("symbols.symdb"));
 
             let mut binds = if file_path.exists() {
-                serde_json::from_slice(&std::fs::read(&file_path).unwrap())
-                    .expect("Invalid symdb file")
+                let mut binds: BindDB = serde_json::from_slice(&std::fs::read(&file_path).unwrap())
+                    .expect("Invalid symdb file");
+                if let Some(old) = binds.follow_base(pair.output.meta.base) {
+                    ReviewQueue::rebase_file(&file_path, &file_path, old, pair.output.meta.base);
+                    println!(
+                        "Rebased symdb from {} to {}",
+                        old.as_hex(),
+                        pair.output.meta.base.as_hex()
+                    );
+                }
+                binds
             } else {
                 BindDB::new(&pair)
             };
 
-            println!("To do!");
-
-            //binds.process(analysis::string_xref_strat(&pair, &binds), &out_file);
-            //binds.process(analysis::call_xref_strat(&pair, &binds), &file_path);
-            //binds.process(analysis::call_block_strat(&pair, &binds), &out_file);
-            binds.process(analysis::block_traverse_strat(&pair, &binds), &file_path);
+            binds.run(&pair, &strats, max_rounds, &file_path, non_interactive);
         }
 
         Command::Strip { file } => {
@@ -73,6 +164,7 @@ fn main() {
             let before_count = binds.binds.len();
 
             binds.binds.retain(|_, x| !matches!(x, Bind::Unverified(_)));
+            binds.evidence.retain(|k, _| binds.binds.contains_key(k));
 
             println!(
                 "Removed {} symbols",
@@ -84,9 +176,91 @@ fn main() {
             std::fs::write(file, serde_json::to_string_pretty(&binds).unwrap()).unwrap();
         }
 
+        Command::Review { file, from, to } => {
+            let pair = from.zip(to).map(|(from, to)| ExecPair {
+                input: ExecDB::load(&from).expect("Invalid exdb file"),
+                output: ExecDB::load(&to).expect("Invalid exdb file"),
+            });
+
+            review::review(&file, pair.as_ref());
+        }
+
+        Command::Tui { symdb, from, to } => {
+            let pair = ExecPair {
+                input: ExecDB::load(&from).expect("Invalid exdb file"),
+                output: ExecDB::load(&to).expect("Invalid exdb file"),
+            };
+
+            tui::run(&symdb, &pair).unwrap();
+        }
+
+        Command::Rebase {
+            file,
+            base,
+            from,
+            output,
+        } => {
+            let output = output.unwrap_or(file.clone());
+            let data = std::fs::read(&file).unwrap();
+
+            // Symdbs are json, which an exdb never is
+            if let Ok(mut binds) = serde_json::from_slice::<BindDB>(&data) {
+                let Some(old) = from.or(binds.base) else {
+                    eprintln!("The symdb doesn't record its base, pass --from");
+                    std::process::exit(1);
+                };
+                binds.rebase(old, base);
+                std::fs::write(&output, serde_json::to_string_pretty(&binds).unwrap()).unwrap();
+                ReviewQueue::rebase_file(&file, &output, old, base);
+                println!("Rebased symdb from {} to {}", old.as_hex(), base.as_hex());
+            } else {
+                let mut exec = ExecDB::load(&file).expect("Invalid exdb or symdb file");
+                let old = exec.meta.base;
+                exec.rebase(base);
+                exec.save(&output).unwrap();
+                println!("Rebased exdb from {} to {}", old.as_hex(), base.as_hex());
+            }
+        }
+
+        Command::List { file, sort } => {
+            let binds: BindDB =
+                serde_json::from_slice(&std::fs::read(&file).unwrap()).expect("Invalid symdb file");
+
+            let mut list: Vec<_> = binds
+                .binds
+                .iter()
+                .map(|(k, v)| (k, v, binds.confidence(k)))
+                .collect();
+            match sort {
+                SortBy::Confidence => list.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap()),
+                SortBy::Name => list.sort_by_key(|x| x.0),
+                SortBy::Address => list.sort_by_key(|x| x.1.get_addr()),
+            }
+
+            for (sym, bind, score) in list {
+                let addr = bind.get_addr();
+                println!(
+                    "{} {} {:?}",
+                    score
+                        .map(|x| format!("{:.2}", x))
+                        .unwrap_or(String::from("----"))
+                        .bright_green(),
+                    demangle(sym).yellow(),
+                    bind
+                );
+
+                review::print_evidence(&binds, sym, addr);
+            }
+        }
+
+        Command::Strategies => {
+            for strat in registry.iter() {
+                println!("{} - {}", strat.name().cyan(), strat.description());
+            }
+        }
+
         Command::Print { exec, addr } => {
-            let exec: ExecDB =
-                pot::from_slice(&std::fs::read(exec).unwrap()).expect("Invalid exdb file");
+            let exec = ExecDB::load(&exec).expect("Invalid exdb file");
             println!("{:#?}", exec.fns.get(&addr));
         }
     }
@@ -1,52 +1,180 @@
+use std::cell::RefCell;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
 use dynfmt::Format;
 use dynfmt::SimpleCurlyFormat;
-use rzpipe::{RzPipe, RzPipeError};
-use serde_json::Value;
+use rzpipe::{RzPipe, RzPipeSpawnOptions};
 use tempfile::NamedTempFile;
 
-pub trait PipeExt {
-    fn cmd_bulk(&mut self, command: &str, offsets: &[u64]) -> Result<String, RzPipeError>;
-    fn cmdj_bulk(&mut self, command: &str, offsets: &[u64]) -> Result<Value, RzPipeError>;
+// Writes offsets to a file rizin can iterate over, and fills it into the command
+fn bulk_command(command: &str, offsets: &[u64]) -> (String, NamedTempFile) {
+    let tmp_file = NamedTempFile::new().unwrap();
+
+    std::fs::write(
+        tmp_file.path(),
+        offsets
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+    .unwrap();
+
+    let command = SimpleCurlyFormat
+        .format(command, &[tmp_file.path().to_str().unwrap()])
+        .unwrap()
+        .to_string();
+    (command, tmp_file)
+}
+
+/// A rizin process running on its own thread
+struct Worker {
+    commands: Sender<String>,
+    results: Receiver<Result<String, String>>,
+}
+
+impl Worker {
+    fn spawn(project: String) -> Self {
+        let (commands, command_rx) = channel::<String>();
+        let (result_tx, results) = channel();
+
+        thread::spawn(move || {
+            let pipe = RzPipe::spawn(
+                "-M",
+                Some(RzPipeSpawnOptions {
+                    exepath: String::from("rizin"),
+                    args: vec!["-p", project.leak()],
+                }),
+            );
+            let mut pipe = match pipe {
+                Ok(x) => x,
+                Err(e) => {
+                    result_tx.send(Err(e.to_string())).ok();
+                    return;
+                }
+            };
+            result_tx.send(Ok(String::new())).ok();
+
+            for command in command_rx {
+                if result_tx
+                    .send(pipe.cmd(&command).map_err(|e| e.to_string()))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            pipe.close();
+        });
+
+        Worker { commands, results }
+    }
+
+    fn send(&self, command: String) -> Result<(), String> {
+        self.commands
+            .send(command)
+            .map_err(|_| String::from("rizin worker exited"))
+    }
+
+    fn recv(&self) -> Result<String, String> {
+        self.results
+            .recv()
+            .map_err(|_| String::from("rizin worker exited"))?
+    }
 }
 
-impl PipeExt for RzPipe {
-    fn cmd_bulk(&mut self, command: &str, offsets: &[u64]) -> Result<String, RzPipeError> {
-        let tmp_file = NamedTempFile::new().unwrap();
-
-        std::fs::write(
-            tmp_file.path(),
-            offsets
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join("\n"),
-        )
-        .unwrap();
-
-        self.cmd(
-            &SimpleCurlyFormat
-                .format(command, &[tmp_file.path().to_str().unwrap()])
-                .unwrap(),
-        )
-    }
-
-    fn cmdj_bulk(&mut self, command: &str, offsets: &[u64]) -> Result<Value, RzPipeError> {
-        let tmp_file = NamedTempFile::new().unwrap();
-
-        std::fs::write(
-            tmp_file.path(),
-            offsets
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join("\n"),
-        )
-        .unwrap();
-
-        self.cmdj(
-            &SimpleCurlyFormat
-                .format(command, &[tmp_file.path().to_str().unwrap()])
-                .unwrap(),
-        )
+/// Several rizin processes with the same project open, which bulk commands are split across
+pub struct PipePool {
+    project: String,
+    workers: RefCell<Vec<Worker>>,
+}
+
+impl PipePool {
+    pub fn spawn(project: &str, jobs: usize) -> Result<Self, String> {
+        let workers: Vec<_> = (0..jobs.max(1))
+            .map(|_| Worker::spawn(project.to_string()))
+            .collect();
+        // Each worker reports once its project has loaded
+        for worker in &workers {
+            worker.recv()?;
+        }
+        Ok(PipePool {
+            project: project.to_string(),
+            workers: RefCell::new(workers),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.borrow().len()
+    }
+
+    // Swaps a worker that failed for a fresh one, since whatever it was in the middle of could
+    // still turn up as the output of its next command
+    fn restart(&self, index: usize) -> Result<(), String> {
+        let worker = Worker::spawn(self.project.clone());
+        worker.recv()?;
+        self.workers.borrow_mut()[index] = worker;
+        Ok(())
+    }
+
+    pub fn cmd(&self, command: &str) -> Result<String, String> {
+        let result = {
+            let workers = self.workers.borrow();
+            workers[0]
+                .send(command.to_string())
+                .and_then(|_| workers[0].recv())
+        };
+        if result.is_err() {
+            self.restart(0)?;
+        }
+        result
+    }
+
+    /// Runs the command over each worker's share of the offsets, giving the output back in
+    /// the order of the offsets
+    pub fn cmd_bulk(&self, command: &str, offsets: &[u64]) -> Result<String, String> {
+        let chunk = offsets.len().div_ceil(self.len()).max(1);
+
+        let mut tmp_files = Vec::new();
+        // (worker index, what it gave back)
+        let mut results = Vec::new();
+        {
+            let workers = self.workers.borrow();
+            let mut dispatched = Vec::new();
+            for (index, offsets) in offsets.chunks(chunk).enumerate() {
+                let (command, tmp_file) = bulk_command(command, offsets);
+                tmp_files.push(tmp_file);
+                if let Err(e) = workers[index].send(command) {
+                    results.push((index, Err(e)));
+                    break;
+                }
+                dispatched.push(index);
+            }
+
+            // Every result is taken before giving up on any, so none are left for the next command
+            for index in dispatched {
+                results.push((index, workers[index].recv()));
+            }
+        }
+        results.sort_by_key(|x| x.0);
+
+        let failed: Vec<_> = results
+            .iter()
+            .filter(|x| x.1.is_err())
+            .map(|x| x.0)
+            .collect();
+        for index in failed {
+            self.restart(index)?;
+        }
+
+        let mut out = String::new();
+        for (_, result) in results {
+            let result = result?;
+            out.push_str(&result);
+            if !result.is_empty() && !result.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        Ok(out)
     }
 }
@@ -0,0 +1,46 @@
+use colored::Colorize;
+use std::io::Write;
+
+use crossterm::event;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+/// Waits for one of `keys` to be pressed and returns it. Ctrl+C exits.
+pub fn choose(msg: &str, hint: &str, keys: &[char]) -> char {
+    print!("{} {}", msg, format!("{} ", hint).dimmed());
+    std::io::stdout().flush().unwrap();
+
+    enable_raw_mode().unwrap();
+
+    loop {
+        let evt = event::read();
+        match evt {
+            Ok(event::Event::Key(event::KeyEvent {
+                code: event::KeyCode::Char('c'),
+                modifiers: event::KeyModifiers::CONTROL,
+                ..
+            })) => {
+                disable_raw_mode().unwrap();
+                std::process::exit(0);
+            }
+            Ok(event::Event::Key(event::KeyEvent {
+                code: event::KeyCode::Char(key),
+                kind: event::KeyEventKind::Press,
+                ..
+            })) if keys.contains(&key) => {
+                disable_raw_mode().unwrap();
+                return key;
+            }
+            _ => (),
+        };
+    }
+}
+
+pub fn confirm(msg: &str) -> bool {
+    if choose(msg, "[y/n]", &['y', 'n']) == 'y' {
+        println!("yes");
+        true
+    } else {
+        println!("no");
+        false
+    }
+}
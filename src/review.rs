@@ -0,0 +1,379 @@
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::compare::print_comparison;
+use crate::db::*;
+use crate::prompt::choose;
+use crate::util::*;
+
+/// How conflicts are settled when nobody is around to answer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Keep whatever was bound first
+    KeepExisting,
+    /// Take the newly proposed address
+    PreferNew,
+    /// Reject every address involved
+    MarkNot,
+    /// Leave the symbol alone and queue the conflict for later
+    Defer,
+}
+
+impl ConflictPolicy {
+    /// The bind for a symbol bound to `old` that was just proposed at `new`
+    pub fn resolve(&self, old: &Bind, new: u64) -> Bind {
+        match (self, old) {
+            (ConflictPolicy::KeepExisting | ConflictPolicy::Defer, _) => old.clone(),
+            (ConflictPolicy::PreferNew, _) => Bind::Unverified(new),
+            (ConflictPolicy::MarkNot, Bind::Not(addrs)) => {
+                let mut addrs = addrs.clone();
+                addrs.push(new);
+                Bind::Not(addrs)
+            }
+            (ConflictPolicy::MarkNot, old) => {
+                Bind::Not(old.get_addr().into_iter().chain([new]).collect())
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Conflict {
+    /// A symbol was proposed somewhere other than where it's bound
+    Address {
+        symbol: String,
+        existing: Bind,
+        proposed: u64,
+    },
+    /// Several symbols were proposed at the same address
+    Duplicate { symbols: Vec<String>, addr: u64 },
+}
+
+impl Conflict {
+    /// Every (symbol, address) pairing the conflict is about
+    pub fn choices(&self) -> Vec<(&str, u64)> {
+        match self {
+            Conflict::Address {
+                symbol,
+                existing,
+                proposed,
+            } => {
+                let existing = match existing {
+                    Bind::Not(_) => None,
+                    x => x.get_addr(),
+                };
+                existing
+                    .into_iter()
+                    .chain([*proposed])
+                    .map(|x| (symbol.as_str(), x))
+                    .collect()
+            }
+            Conflict::Duplicate { symbols, addr } => {
+                symbols.iter().map(|x| (x.as_str(), *addr)).collect()
+            }
+        }
+    }
+
+    /// Moves the addresses by `delta`
+    fn shift(&mut self, delta: u64) {
+        match self {
+            Conflict::Address {
+                existing, proposed, ..
+            } => {
+                existing.shift(delta);
+                *proposed = proposed.wrapping_add(delta);
+            }
+            Conflict::Duplicate { addr, .. } => *addr = addr.wrapping_add(delta),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewEntry {
+    pub conflict: Conflict,
+    /// The policy that settled the conflict, or None if it still needs a look
+    pub resolution: Option<ConflictPolicy>,
+}
+
+/// Conflicts found while processing, kept in a file next to the symdb
+#[derive(Serialize, Deserialize, Default)]
+pub struct ReviewQueue {
+    pub entries: Vec<ReviewEntry>,
+}
+
+impl ReviewQueue {
+    pub fn path_for(symdb: &Path) -> PathBuf {
+        symdb.with_extension("review")
+    }
+
+    pub fn load(path: &Path) -> Self {
+        if path.exists() {
+            serde_json::from_slice(&std::fs::read(path).unwrap()).expect("Invalid review file")
+        } else {
+            ReviewQueue::default()
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+
+    /// Adds a conflict, unless it was already recorded with the same resolution
+    pub fn push(&mut self, conflict: Conflict, resolution: Option<ConflictPolicy>) {
+        if self
+            .entries
+            .iter()
+            .any(|x| x.conflict == conflict && x.resolution == resolution)
+        {
+            return;
+        }
+
+        self.entries.push(ReviewEntry {
+            conflict,
+            resolution,
+        });
+    }
+
+    /// Drops a conflict that was waiting for review
+    pub fn remove(&mut self, conflict: &Conflict) {
+        self.entries
+            .retain(|x| x.resolution.is_some() || &x.conflict != conflict);
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &ReviewEntry> {
+        self.entries.iter().filter(|x| x.resolution.is_none())
+    }
+
+    /// Moves every address from an image loaded at `from` to one loaded at `to`
+    pub fn rebase(&mut self, from: u64, to: u64) {
+        let delta = to.wrapping_sub(from);
+        self.entries
+            .iter_mut()
+            .for_each(|x| x.conflict.shift(delta));
+    }
+
+    /// Rebases the queue kept next to `symdb` along with it, writing it next to `output`
+    pub fn rebase_file(symdb: &Path, output: &Path, from: u64, to: u64) {
+        let path = ReviewQueue::path_for(symdb);
+        if !path.exists() {
+            return;
+        }
+        let mut queue = ReviewQueue::load(&path);
+        queue.rebase(from, to);
+        queue.save(&ReviewQueue::path_for(output));
+    }
+}
+
+/// Where conflicts found while processing go, saving the queue after every change
+pub struct Conflicts {
+    /// Settle conflicts with this instead of asking
+    pub policy: Option<ConflictPolicy>,
+    pub queue: ReviewQueue,
+    path: PathBuf,
+}
+
+impl Conflicts {
+    pub fn new(symdb: &Path, policy: Option<ConflictPolicy>) -> Self {
+        let path = ReviewQueue::path_for(symdb);
+        Conflicts {
+            policy,
+            queue: ReviewQueue::load(&path),
+            path,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn push(&mut self, conflict: Conflict, resolution: Option<ConflictPolicy>) {
+        self.queue.push(conflict, resolution);
+        self.queue.save(&self.path);
+    }
+
+    pub fn remove(&mut self, conflict: &Conflict) {
+        self.queue.remove(conflict);
+        self.queue.save(&self.path);
+    }
+}
+
+/// Lines saying why a symbol was proposed, optionally only at one address
+pub fn evidence_lines(binds: &BindDB, sym: &str, addr: Option<u64>) -> Vec<String> {
+    binds
+        .evidence
+        .get(sym)
+        .into_iter()
+        .flatten()
+        .filter(|x| addr.is_none() || addr == Some(x.addr))
+        .map(|evidence| {
+            format!(
+                "    {} {} at {} in round {}{}",
+                format!("{:.2}", evidence.score).dimmed(),
+                evidence.strategy.cyan(),
+                evidence.addr.as_hex().blue(),
+                evidence.round,
+                evidence
+                    .anchor
+                    .as_ref()
+                    .map(|x| format!(" from {}", demangle(x)))
+                    .unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+pub fn print_evidence(binds: &BindDB, sym: &str, addr: Option<u64>) {
+    for line in evidence_lines(binds, sym, addr) {
+        println!("{}", line);
+    }
+}
+
+// Adds an address to the ones a symbol is known not to be at
+fn reject(binds: &mut BindDB, sym: &str, addr: u64) {
+    match binds.binds.get_mut(sym) {
+        Some(Bind::Not(x)) => {
+            if !x.contains(&addr) {
+                x.push(addr);
+            }
+        }
+        Some(Bind::Verified(_) | Bind::Inline) => {}
+        _ => {
+            binds.binds.insert(sym.to_string(), Bind::Not(vec![addr]));
+        }
+    }
+}
+
+/// Walks the conflicts waiting in a symdb's review queue, asking how to settle each one.
+/// The functions involved are shown side by side if the exdbs are given.
+pub fn review(symdb: &Path, pair: Option<&ExecPair>) {
+    let mut binds: BindDB =
+        serde_json::from_slice(&std::fs::read(symdb).unwrap()).expect("Invalid symdb file");
+    let queue_file = ReviewQueue::path_for(symdb);
+    let mut queue = ReviewQueue::load(&queue_file);
+
+    let pending: Vec<_> = queue.pending().map(|x| x.conflict.clone()).collect();
+    let total = pending.len();
+
+    println!(
+        "{} conflicts waiting for review",
+        total.to_string().bright_green()
+    );
+
+    for (i, conflict) in pending.into_iter().enumerate() {
+        let choices = conflict.choices();
+
+        // Anything settled since it was queued doesn't need another look
+        if choices.iter().all(|(sym, _)| {
+            matches!(
+                binds.binds.get(*sym),
+                Some(Bind::Verified(_) | Bind::Inline)
+            )
+        }) {
+            queue.remove(&conflict);
+            continue;
+        }
+
+        println!();
+        println!("{}", format!("[{}/{}]", i + 1, total).bold());
+
+        if let Some(pair) = pair {
+            let names = binds.output_names();
+            for (sym, addr) in &choices {
+                print_comparison(pair, &names, sym, *addr);
+            }
+        }
+
+        for (n, (sym, addr)) in choices.iter().enumerate() {
+            println!(
+                "{} {} at {} (currently {:?})",
+                format!("{}.", n + 1).bold(),
+                demangle(sym).yellow(),
+                addr.as_hex().blue(),
+                binds.binds.get(*sym)
+            );
+            print_evidence(&binds, sym, Some(*addr));
+        }
+
+        let digits: Vec<_> = (1..=choices.len().min(9))
+            .filter_map(|x| char::from_digit(x as u32, 10))
+            .collect();
+        let mut keys = digits.clone();
+        keys.extend(['r', 's', 'q']);
+
+        let hint = if let Conflict::Address { .. } = conflict {
+            keys.push('i');
+            "[number to verify/r to reject all/i for inline/s to skip/q to quit]"
+        } else {
+            "[number to verify/r to reject all/s to skip/q to quit]"
+        };
+
+        let key = choose("What should be done?", hint, &keys);
+        println!("{}", key);
+
+        match key {
+            'q' => break,
+            's' => continue,
+            'r' => {
+                for (sym, addr) in &choices {
+                    reject(&mut binds, sym, *addr);
+                }
+            }
+            'i' => {
+                for (sym, _) in &choices {
+                    binds.binds.insert(sym.to_string(), Bind::Inline);
+                }
+            }
+            x => {
+                let (sym, addr) = choices[x.to_digit(10).unwrap() as usize - 1];
+                for (other_sym, other_addr) in &choices {
+                    if *other_sym != sym {
+                        reject(&mut binds, other_sym, *other_addr);
+                    }
+                }
+                binds.binds.insert(sym.to_string(), Bind::Verified(addr));
+            }
+        }
+
+        queue.remove(&conflict);
+        queue.save(&queue_file);
+        std::fs::write(symdb, serde_json::to_string_pretty(&binds).unwrap()).unwrap();
+    }
+
+    queue.save(&queue_file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_follows_the_policy() {
+        let old = Bind::Unverified(0x10);
+        assert_eq!(ConflictPolicy::KeepExisting.resolve(&old, 0x20), old);
+        assert_eq!(ConflictPolicy::Defer.resolve(&old, 0x20), old);
+        assert_eq!(
+            ConflictPolicy::PreferNew.resolve(&old, 0x20),
+            Bind::Unverified(0x20)
+        );
+        assert_eq!(
+            ConflictPolicy::MarkNot.resolve(&old, 0x20),
+            Bind::Not(vec![0x10, 0x20])
+        );
+    }
+
+    #[test]
+    fn resolve_mark_not_extends_rejections() {
+        let old = Bind::Not(vec![0x10]);
+        assert_eq!(
+            ConflictPolicy::MarkNot.resolve(&old, 0x20),
+            Bind::Not(vec![0x10, 0x20])
+        );
+        assert_eq!(
+            ConflictPolicy::MarkNot.resolve(&Bind::Inline, 0x20),
+            Bind::Not(vec![0x20])
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::analysis::{BlockTraverse, CallBlock, CallXref, StringXref};
+use crate::cfg::{CfgAlign, StructHash};
+use crate::db::*;
+
+/// A bind proposed by a strategy, along with how it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub addr: u64,
+    /// How sure the strategy is, from 0 to 1
+    pub score: f32,
+    /// The bound symbol or string that led to this candidate
+    pub anchor: Option<String>,
+}
+
+/// A way of proposing new binds from the binds already known
+pub trait Strategy {
+    /// Name used to select the strategy on the command line
+    fn name(&self) -> &str;
+
+    /// One line summary shown when listing strategies
+    fn description(&self) -> &str;
+
+    /// Proposes binds of input symbols to output function addresses
+    fn run(&self, pair: &ExecPair, binds: &BindDB) -> HashMap<String, Candidate>;
+}
+
+/// Every strategy `run` can choose from, in the order they were registered
+pub struct Registry {
+    strats: Vec<Box<dyn Strategy>>,
+}
+
+impl Registry {
+    /// A registry without any strategies, not even the builtin ones
+    pub fn new() -> Self {
+        Registry { strats: Vec::new() }
+    }
+
+    /// Adds a strategy, replacing any registered strategy with the same name
+    pub fn register(&mut self, strat: impl Strategy + 'static) -> &mut Self {
+        self.strats.retain(|x| x.name() != strat.name());
+        self.strats.push(Box::new(strat));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Strategy> {
+        self.strats
+            .iter()
+            .find(|x| x.name() == name)
+            .map(|x| x.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Strategy> {
+        self.strats.iter().map(|x| x.as_ref())
+    }
+
+    /// Looks up strategies by name, keeping the order they were asked for in
+    pub fn select(&self, names: &[String]) -> Result<Vec<&dyn Strategy>, String> {
+        names
+            .iter()
+            .map(|x| {
+                self.get(x).ok_or_else(|| {
+                    format!(
+                        "Unknown strategy {} (expected one of: {})",
+                        x,
+                        self.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for Registry {
+    /// A registry with the builtin strategies
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry
+            .register(StringXref)
+            .register(CallXref)
+            .register(CallBlock)
+            .register(BlockTraverse)
+            .register(CfgAlign)
+            .register(StructHash);
+        registry
+    }
+}
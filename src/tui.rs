@@ -0,0 +1,421 @@
+use colored::Colorize;
+use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style, terminal};
+use regex::Regex;
+
+use crate::compare::comparison_lines;
+use crate::db::*;
+use crate::review::{evidence_lines, ReviewQueue};
+use crate::util::*;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Filter {
+    All,
+    Verified,
+    Unverified,
+    Not,
+    Inline,
+}
+
+impl Filter {
+    fn next(self) -> Self {
+        match self {
+            Filter::All => Filter::Verified,
+            Filter::Verified => Filter::Unverified,
+            Filter::Unverified => Filter::Not,
+            Filter::Not => Filter::Inline,
+            Filter::Inline => Filter::All,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Filter::All => "all",
+            Filter::Verified => "verified",
+            Filter::Unverified => "unverified",
+            Filter::Not => "not",
+            Filter::Inline => "inline",
+        }
+    }
+
+    fn matches(self, bind: &Bind) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Verified => matches!(bind, Bind::Verified(_)),
+            Filter::Unverified => matches!(bind, Bind::Unverified(_)),
+            Filter::Not => matches!(bind, Bind::Not(_)),
+            Filter::Inline => matches!(bind, Bind::Inline),
+        }
+    }
+}
+
+// What the bottom line is asking for
+#[derive(Clone, Copy)]
+enum Prompt {
+    Search,
+    Range,
+    Address,
+}
+
+impl Prompt {
+    fn label(self) -> &'static str {
+        match self {
+            Prompt::Search => "Name regex: ",
+            Prompt::Range => "Address range (start-end): ",
+            Prompt::Address => "New address: ",
+        }
+    }
+}
+
+const HELP: &str = "j/k move  tab filter  / search  a range  v verify  u unverify  r reject  i inline  e edit  q quit";
+
+struct Tui<'a> {
+    pair: &'a ExecPair,
+    binds: BindDB,
+    path: &'a Path,
+    demangled: HashMap<String, String>,
+    names: HashMap<u64, String>,
+    // Symbols passing the filters, sorted by demangled name
+    visible: Vec<String>,
+    selected: usize,
+    scroll: usize,
+    filter: Filter,
+    search: Option<Regex>,
+    range: Option<(u64, u64)>,
+    prompt: Option<(Prompt, String)>,
+    status: String,
+}
+
+impl<'a> Tui<'a> {
+    fn new(binds: BindDB, pair: &'a ExecPair, path: &'a Path) -> Self {
+        let mut tui = Tui {
+            pair,
+            demangled: binds
+                .binds
+                .keys()
+                .map(|x| (x.clone(), demangle(x)))
+                .collect(),
+            names: binds.output_names(),
+            binds,
+            path,
+            visible: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            filter: Filter::All,
+            search: None,
+            range: None,
+            prompt: None,
+            status: String::from(HELP),
+        };
+        tui.refresh();
+        tui
+    }
+
+    fn refresh(&mut self) {
+        let mut visible: Vec<_> = self
+            .binds
+            .binds
+            .iter()
+            .filter(|(_, v)| self.filter.matches(v))
+            .filter(|(_, v)| match self.range {
+                Some((start, end)) => v.get_addr().is_some_and(|x| x >= start && x <= end),
+                None => true,
+            })
+            .filter(|(k, _)| match &self.search {
+                Some(x) => x.is_match(k) || x.is_match(&self.demangled[*k]),
+                None => true,
+            })
+            .map(|(k, _)| k.clone())
+            .collect();
+        visible.sort_by(|a, b| self.demangled[a].cmp(&self.demangled[b]));
+
+        self.visible = visible;
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+    }
+
+    fn selected_sym(&self) -> Option<&String> {
+        self.visible.get(self.selected)
+    }
+
+    // Changes the selected symbol's bind and saves the symdb
+    fn set_bind(&mut self, bind: Bind) {
+        let Some(sym) = self.selected_sym().cloned() else {
+            return;
+        };
+
+        self.status = format!("{} is now {:?}", self.demangled[&sym], bind);
+        self.binds.binds.insert(sym, bind);
+        self.names = self.binds.output_names();
+        std::fs::write(
+            self.path,
+            serde_json::to_string_pretty(&self.binds).unwrap(),
+        )
+        .unwrap();
+        self.refresh();
+    }
+
+    fn submit(&mut self, prompt: Prompt, text: &str) {
+        let text = text.trim();
+        match prompt {
+            Prompt::Search if text.is_empty() => self.search = None,
+            Prompt::Search => match Regex::new(text) {
+                Ok(x) => self.search = Some(x),
+                Err(e) => self.status = format!("Bad regex: {}", e),
+            },
+            Prompt::Range if text.is_empty() => self.range = None,
+            Prompt::Range => {
+                match text
+                    .split_once('-')
+                    .and_then(|(x, y)| (parse_hex(x)?, parse_hex(y)?).as_some())
+                {
+                    Some(x) => self.range = Some(x),
+                    None => self.status = format!("Bad address range: {}", text),
+                }
+            }
+            Prompt::Address => match parse_hex(text) {
+                Some(x) => self.set_bind(Bind::Verified(x)),
+                None => self.status = format!("Bad address: {}", text),
+            },
+        }
+        self.refresh();
+    }
+
+    // Returns false once the user wants out
+    fn handle_key(&mut self, key: KeyEvent, page: usize) -> bool {
+        if let Some((prompt, mut text)) = self.prompt.take() {
+            match key.code {
+                KeyCode::Enter => self.submit(prompt, &text),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.prompt = Some((prompt, text));
+                }
+                KeyCode::Char(c) => {
+                    text.push(c);
+                    self.prompt = Some((prompt, text));
+                }
+                _ => self.prompt = Some((prompt, text)),
+            }
+            return true;
+        }
+
+        let last = self.visible.len().saturating_sub(1);
+        let addr = self
+            .selected_sym()
+            .and_then(|x| self.binds.binds.get(x))
+            .and_then(|x| x.get_addr());
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown => self.selected = (self.selected + page).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(page),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.selected = last,
+            KeyCode::Tab => {
+                self.filter = self.filter.next();
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.prompt = Some((Prompt::Search, String::new())),
+            KeyCode::Char('a') => self.prompt = Some((Prompt::Range, String::new())),
+            KeyCode::Char('e') => self.prompt = Some((Prompt::Address, String::new())),
+            KeyCode::Char('v') => match addr {
+                Some(x) => self.set_bind(Bind::Verified(x)),
+                None => self.status = String::from("Nothing to verify without an address"),
+            },
+            KeyCode::Char('u') => match addr {
+                Some(x) => self.set_bind(Bind::Unverified(x)),
+                None => self.status = String::from("Nothing to unverify without an address"),
+            },
+            KeyCode::Char('r') => match addr {
+                Some(x) => self.set_bind(Bind::Not(vec![x])),
+                None => self.status = String::from("Nothing to reject without an address"),
+            },
+            KeyCode::Char('i') => self.set_bind(Bind::Inline),
+            _ => {}
+        }
+        true
+    }
+
+    fn detail_lines(&self, width: usize) -> Vec<String> {
+        let Some(sym) = self.selected_sym() else {
+            return vec![String::from("No symbols match")];
+        };
+        let bind = &self.binds.binds[sym];
+
+        let mut lines = vec![
+            self.demangled[sym].yellow().bold().to_string(),
+            sym.dimmed().to_string(),
+            format!("{:?}", bind),
+        ];
+
+        if let Some(addr) = bind.get_addr() {
+            lines.push(String::new());
+            lines.extend(comparison_lines(
+                self.pair,
+                &self.names,
+                sym,
+                addr,
+                width.saturating_sub(3) / 2,
+            ));
+        }
+
+        let evidence = evidence_lines(&self.binds, sym, None);
+        if !evidence.is_empty() {
+            lines.push(String::new());
+            lines.push("Evidence".bold().to_string());
+            lines.extend(evidence);
+        }
+
+        lines
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let list_width = width * 2 / 5;
+        let rows = height.saturating_sub(2);
+
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        let header = format!(
+            "symbo | {} of {} symbols | filter: {} | search: {} | range: {}",
+            self.visible.len(),
+            self.binds.binds.len(),
+            self.filter.name(),
+            self.search.as_ref().map(|x| x.as_str()).unwrap_or("none"),
+            self.range
+                .map(|(x, y)| format!("{}-{}", x.as_hex(), y.as_hex()))
+                .unwrap_or(String::from("none"))
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            style::Print(fit(&header, width).reversed())
+        )?;
+
+        for (row, sym) in self.visible.iter().skip(self.scroll).take(rows).enumerate() {
+            let bind = &self.binds.binds[sym];
+            let tag = match bind {
+                Bind::Verified(_) => "V".green(),
+                Bind::Unverified(_) => "U".yellow(),
+                Bind::Not(_) => "N".red(),
+                Bind::Inline => "I".blue(),
+            };
+            let text = fit(
+                &format!(
+                    " {:>12} {}",
+                    bind.get_addr().map(|x| x.as_hex()).unwrap_or_default(),
+                    self.demangled[sym]
+                ),
+                list_width.saturating_sub(1),
+            );
+            let text = if self.scroll + row == self.selected {
+                text.reversed()
+            } else {
+                text.normal()
+            };
+
+            queue!(
+                out,
+                cursor::MoveTo(0, (row + 1) as u16),
+                style::Print(tag),
+                style::Print(text)
+            )?;
+        }
+
+        for (row, line) in self
+            .detail_lines(width.saturating_sub(list_width + 2))
+            .into_iter()
+            .take(rows)
+            .enumerate()
+        {
+            queue!(
+                out,
+                cursor::MoveTo(list_width as u16, (row + 1) as u16),
+                style::Print("│ ".dimmed()),
+                style::Print(line)
+            )?;
+        }
+
+        let footer = match &self.prompt {
+            Some((prompt, text)) => format!("{}{}", prompt.label(), text),
+            None => self.status.clone(),
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            style::Print(fit(&footer, width))
+        )?;
+
+        out.flush()
+    }
+}
+
+// Cuts or pads text to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let mut text: String = text.chars().take(width).collect();
+    let len = text.chars().count();
+    text.push_str(&" ".repeat(width - len));
+    text
+}
+
+/// Opens a full screen browser for a symdb, saving any edits back to it
+pub fn run(symdb: &Path, pair: &ExecPair) -> std::io::Result<()> {
+    let mut binds: BindDB =
+        serde_json::from_slice(&std::fs::read(symdb)?).expect("Invalid symdb file");
+    if let Some(old) = binds.follow_base(pair.output.meta.base) {
+        // Saved straight away so the symdb and its review queue stay in step
+        std::fs::write(symdb, serde_json::to_string_pretty(&binds).unwrap())?;
+        ReviewQueue::rebase_file(symdb, symdb, old, pair.output.meta.base);
+    }
+    let mut tui = Tui::new(binds, pair, symdb);
+    let mut out = stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(
+        out,
+        terminal::EnterAlternateScreen,
+        terminal::DisableLineWrap,
+        cursor::Hide
+    )?;
+
+    let result = (|| loop {
+        tui.draw(&mut out)?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(());
+            }
+
+            let page = terminal::size()?.1.saturating_sub(2) as usize;
+            if !tui.handle_key(key, page) {
+                return Ok(());
+            }
+        }
+    })();
+
+    execute!(
+        out,
+        cursor::Show,
+        terminal::EnableLineWrap,
+        terminal::LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
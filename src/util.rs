@@ -1,4 +1,7 @@
 use std::fmt::Display;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
 
 pub trait AsHex {
     fn as_hex(&self) -> String;
@@ -10,6 +13,30 @@ impl AsHex for u64 {
     }
 }
 
+/// Parses a hex address typed by a user, with or without the 0x
+pub fn parse_hex(hex: &str) -> Option<u64> {
+    let hex = hex.trim();
+    u64::from_str_radix(hex.strip_prefix("0x").unwrap_or(hex), 16).ok()
+}
+
+/// `parse_hex` for clap, with an error it can show
+pub fn parse_base(x: &str) -> Result<u64, String> {
+    parse_hex(x).ok_or(format!("Invalid address: {}", x))
+}
+
+/// Demangles an Itanium symbol, or gives it back unchanged if it isn't one
+pub fn demangle(sym: &str) -> String {
+    cpp_demangle::Symbol::new(sym)
+        .map(|x| x.to_string())
+        .unwrap_or(sym.to_string())
+}
+
+/// Hex sha256 of a file's contents
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let digest = Sha256::digest(std::fs::read(path)?);
+    Ok(digest.iter().map(|x| format!("{:02x}", x)).collect())
+}
+
 pub trait AsSome {
     fn as_some(self) -> Option<Self>
     where